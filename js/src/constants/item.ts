@@ -1,2 +1,3 @@
 export const PREFIX: string = "item";
 export const MARKER: string = "marker";
+export const CHILDREN: string = "children";
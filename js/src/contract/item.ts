@@ -19,6 +19,7 @@ import {
   getCraftItemEscrow,
   getEdition,
   getItemActivationMarker,
+  getItemClassChildrenPDA,
   getItemEscrow,
   getItemPDA,
   getMetadata,
@@ -1207,6 +1208,7 @@ export class ItemProgram {
       accounts.itemMint,
       args.classIndex
     );
+    const parent = accounts.parent || itemClassKey;
 
     await this.program.methods
       .createItemClass(args)
@@ -1215,7 +1217,9 @@ export class ItemProgram {
         itemMint: accounts.itemMint,
         metadata: await getMetadata(accounts.itemMint),
         edition: await getEdition(accounts.itemMint),
-        parent: accounts.parent || itemClassKey,
+        parent,
+        // Unused without a parent, but still has to be the derived address.
+        parentChildren: (await getItemClassChildrenPDA(parent))[0],
         payer: (this.program.provider as AnchorProvider).wallet.publicKey,
         systemProgram: SystemProgram.programId,
         rent: web3.SYSVAR_RENT_PUBKEY,
@@ -1279,6 +1283,9 @@ export class ItemProgram {
         itemClass: itemClassKey,
         parent: accounts.parent || web3.SystemProgram.programId,
         itemMint: accounts.itemMint,
+        payer: (this.program.provider as AnchorProvider).wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+        rent: web3.SYSVAR_RENT_PUBKEY,
       })
       .remainingAccounts(remainingAccounts)
       .rpc();
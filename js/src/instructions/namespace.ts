@@ -94,6 +94,9 @@ export class Instruction extends SolKitInstruction {
           namespace: namespacePDA,
           namespaceToken: accounts.namespaceToken,
           tokenHolder: accounts.tokenHolder,
+          payer: (this.program.client.provider as AnchorProvider).wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: web3.SYSVAR_RENT_PUBKEY,
         })
         .remainingAccounts(remainingAccounts)
         .instruction(),
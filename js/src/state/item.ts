@@ -13,12 +13,56 @@ import {
 
 extendBorsh();
 
+// Item classes written before the layout was versioned have no version byte, their data
+// starts straight away with the settings, whose first byte is an option tag of 0 or 1.
+export const LEGACY_LAYOUT_VERSION = 1;
+export const LAYOUT_VERSION = 2;
+
+const NAMESPACE_AND_INDEX_SIZE = 34;
+
+const getItemClassDataOffset = (itemClass: ItemClass): number => {
+  let offset = 8 + 1; // discriminator, namespaces option
+  if (itemClass.namespaces)
+    offset += 4 + itemClass.namespaces.length * NAMESPACE_AND_INDEX_SIZE;
+  for (const key of [
+    itemClass.parent,
+    itemClass.mint,
+    itemClass.metadata,
+    itemClass.edition,
+  ]) {
+    offset += key ? 33 : 1;
+  }
+  return offset + 1 + 8; // bump, existing children
+};
+
 export const decodeItemClass = (buffer: Buffer): ItemClass => {
   const metadata = deserializeUnchecked(
     ITEM_SCHEMA,
     ItemClass,
     buffer
   ) as ItemClass;
+
+  const offset = getItemClassDataOffset(metadata);
+  if (buffer[offset] > LEGACY_LAYOUT_VERSION) {
+    metadata.layoutVersion = buffer[offset];
+    metadata.itemClassData = deserializeUnchecked(
+      ITEM_SCHEMA,
+      ItemClassData,
+      buffer.slice(offset + 1)
+    ) as ItemClassData;
+  } else {
+    const legacy = deserializeUnchecked(
+      ITEM_SCHEMA,
+      LegacyItemClassData,
+      buffer.slice(offset)
+    ) as LegacyItemClassData;
+    metadata.layoutVersion = LEGACY_LAYOUT_VERSION;
+    metadata.itemClassData = new ItemClassData({
+      category: null,
+      settings: legacy.settings,
+      config: legacy.config,
+    });
+  }
   return metadata;
 };
 
@@ -64,7 +108,34 @@ export class ItemClassSettings {
   }
 }
 
+export class ItemCategory {
+  category: string;
+  inherited: InheritanceState;
+
+  constructor(args: { category: string; inherited: InheritanceState }) {
+    this.category = args.category;
+    this.inherited = args.inherited;
+  }
+}
+
 export class ItemClassData {
+  // Always null on item classes from before LAYOUT_VERSION.
+  category: null | ItemCategory;
+  settings: ItemClassSettings;
+  config: ItemClassConfig;
+
+  constructor(args: {
+    category: null | ItemCategory;
+    settings: ItemClassSettings;
+    config: ItemClassConfig;
+  }) {
+    this.category = args.category;
+    this.settings = args.settings;
+    this.config = args.config;
+  }
+}
+
+class LegacyItemClassData {
   settings: ItemClassSettings;
   config: ItemClassConfig;
 
@@ -213,6 +284,7 @@ export class ItemClass {
   edition: web3.PublicKey | null;
   bump: number;
   existingChildren: BN;
+  layoutVersion: number;
   itemClassData: ItemClassData;
 
   constructor(args: {
@@ -224,6 +296,7 @@ export class ItemClass {
     edition: web3.PublicKey | null;
     bump: number;
     existingChildren: BN;
+    layoutVersion: number;
     itemClassData: ItemClassData;
   }) {
     this.key = args.key;
@@ -234,6 +307,7 @@ export class ItemClass {
     this.edition = args.edition;
     this.bump = args.bump;
     this.existingChildren = args.existingChildren;
+    this.layoutVersion = args.layoutVersion;
     this.itemClassData = args.itemClassData;
   }
 }
@@ -358,7 +432,7 @@ export const ITEM_SCHEMA = new Map<any, any>([
         ["edition", { kind: "option", type: "pubkey" }],
         ["bump", "u8"],
         ["existingChildren", "u64"],
-        ["itemClassData", ItemClassData],
+        // The item class data comes after an optional version byte, see decodeItemClass.
       ],
     },
   ],
@@ -368,11 +442,32 @@ export const ITEM_SCHEMA = new Map<any, any>([
     {
       kind: "struct",
       fields: [
+        ["category", { kind: "option", type: ItemCategory }],
         ["settings", ItemClassSettings],
         ["config", ItemClassConfig],
       ],
     },
   ],
+  [
+    LegacyItemClassData,
+    {
+      kind: "struct",
+      fields: [
+        ["settings", ItemClassSettings],
+        ["config", ItemClassConfig],
+      ],
+    },
+  ],
+  [
+    ItemCategory,
+    {
+      kind: "struct",
+      fields: [
+        ["category", "string"],
+        ["inherited", "u8"],
+      ],
+    },
+  ],
   [
     Root,
     {
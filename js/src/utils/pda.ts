@@ -8,7 +8,7 @@ import {
   MATCHES_ID,
   PLAYER_ID,
 } from "../constants/programIds";
-import { PREFIX as ITEM_PREFIX, MARKER } from "../constants/item";
+import { PREFIX as ITEM_PREFIX, MARKER, CHILDREN } from "../constants/item";
 import { PREFIX as MATCHES_PREFIX } from "../constants/matches";
 import { PREFIX as NAMESPACE_PREFIX } from "../constants/namespace";
 import { PREFIX as PLAYER_PREFIX } from "../constants/player";
@@ -87,6 +87,15 @@ export const getPlayerPDA = async (
   );
 };
 
+export const getItemClassChildrenPDA = async (
+  parent: web3.PublicKey
+): Promise<[web3.PublicKey, number]> => {
+  return await web3.PublicKey.findProgramAddress(
+    [Buffer.from(ITEM_PREFIX), parent.toBuffer(), Buffer.from(CHILDREN)],
+    ITEM_ID
+  );
+};
+
 export const getItemActivationMarker = async (args: {
   itemMint: web3.PublicKey;
   index: BN;
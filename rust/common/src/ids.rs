@@ -0,0 +1,87 @@
+//! Program IDs for every raindrops program, picked by the `localnet`, `devnet` or `mainnet`
+//! feature. Mainnet is used when no cluster feature is set, and is the fallback for any ID
+//! not overridden on localnet or devnet. Mainnet can't be combined with the others.
+
+use anchor_lang::prelude::Pubkey;
+
+#[cfg(all(feature = "mainnet", any(feature = "localnet", feature = "devnet")))]
+compile_error!("The mainnet feature can't be combined with the localnet or devnet feature");
+
+mod mainnet {
+    pub const ITEM_ID: &str = "itemX1XWs9dK8T2Zca4vEEPfCAhRc7yvYFntPjTTVx6";
+    pub const MATCH_ID: &str = "mtchsiT6WoLQ62fwCoiHMCfXJzogtfru4ovY8tXKrjJ";
+    pub const NAMESPACE_ID: &str = "nameAxQRRBnd4kLfsVoZBBXfrByZdZTkh8mULLxLyqV";
+    pub const PLAYER_ID: &str = "p1exdMJcjVao65QdewkaZRUnU6VPSXhus9n2GzWfh98";
+    pub const STAKING_ID: &str = "stk9HFnKhZN2PZjnn5C4wTzmeiAEgsDkbqnHkNjX1Z4";
+}
+
+#[cfg(any(
+    feature = "mainnet",
+    not(any(feature = "localnet", feature = "devnet"))
+))]
+pub use mainnet::*;
+
+// Test validators are usually loaded with freshly generated keypairs, and devnet deployments
+// need not share the mainnet addresses, so on either cluster each ID can be overridden at build
+// time, ie RAINDROPS_ITEM_ID=<key> anchor build -- --features devnet
+#[cfg(any(feature = "localnet", feature = "devnet"))]
+mod overridden {
+    pub const ITEM_ID: &str = match option_env!("RAINDROPS_ITEM_ID") {
+        Some(id) => id,
+        None => super::mainnet::ITEM_ID,
+    };
+    pub const MATCH_ID: &str = match option_env!("RAINDROPS_MATCH_ID") {
+        Some(id) => id,
+        None => super::mainnet::MATCH_ID,
+    };
+    pub const NAMESPACE_ID: &str = match option_env!("RAINDROPS_NAMESPACE_ID") {
+        Some(id) => id,
+        None => super::mainnet::NAMESPACE_ID,
+    };
+    pub const PLAYER_ID: &str = match option_env!("RAINDROPS_PLAYER_ID") {
+        Some(id) => id,
+        None => super::mainnet::PLAYER_ID,
+    };
+    pub const STAKING_ID: &str = match option_env!("RAINDROPS_STAKING_ID") {
+        Some(id) => id,
+        None => super::mainnet::STAKING_ID,
+    };
+}
+
+#[cfg(all(
+    any(feature = "localnet", feature = "devnet"),
+    not(feature = "mainnet")
+))]
+pub use overridden::*;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Decodes one of the IDs above at compile time, so programs can hand it to declare_id!.
+pub const fn program_id(id: &str) -> Pubkey {
+    let input = id.as_bytes();
+    let mut bytes = [0u8; 32];
+    let mut i = 0;
+    while i < input.len() {
+        let mut digit = 0;
+        while digit < BASE58_ALPHABET.len() && BASE58_ALPHABET[digit] != input[i] {
+            digit += 1;
+        }
+        if digit == BASE58_ALPHABET.len() {
+            panic!("Program id is not valid base58");
+        }
+
+        let mut carry = digit as u32;
+        let mut j = bytes.len();
+        while j > 0 {
+            j -= 1;
+            carry += bytes[j] as u32 * 58;
+            bytes[j] = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        if carry != 0 {
+            panic!("Program id is longer than 32 bytes");
+        }
+        i += 1;
+    }
+    Pubkey::new_from_array(bytes)
+}
@@ -0,0 +1,7 @@
+//! PDA seed prefixes, one per program. Every PDA a program derives starts with its prefix.
+
+pub const ITEM_PREFIX: &str = "item";
+pub const MATCHES_PREFIX: &str = "matches";
+pub const NAMESPACE_PREFIX: &str = "namespace";
+pub const PLAYER_PREFIX: &str = "player";
+pub const STAKING_PREFIX: &str = "staking";
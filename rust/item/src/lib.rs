@@ -2,30 +2,43 @@ pub mod utils;
 
 use {
     crate::utils::{
-        assert_builder_must_be_holder_check, assert_is_ata, assert_keys_equal,
-        assert_metadata_valid, assert_mint_authority_matches_mint, assert_permissiveness_access,
-        assert_valid_item_settings_for_edition_type, close_token_account, get_item_usage,
-        propagate_item_class_data_fields_to_item_data, sighash, spl_token_burn, spl_token_mint_to,
-        spl_token_transfer, transfer_mint_authority, update_item_class_with_inherited_information,
-        verify, verify_and_affect_item_state_update, verify_component, verify_cooldown, write_data,
-        AssertPermissivenessAccessArgs, GetItemUsageArgs, TokenBurnParams, TokenTransferParams,
-        TransferMintAuthorityArgs, VerifyAndAffectItemStateUpdateArgs, VerifyComponentArgs,
-        VerifyCooldownArgs,
+        add_item_class_child, apply_item_class_data_patch, assert_builder_must_be_holder_check,
+        assert_is_ata, assert_item_class_mint, assert_keys_equal, assert_metadata_valid,
+        assert_mint_authority_matches_mint, assert_owned_by, assert_permissiveness_access,
+        close_token_account, get_item_usage, permissiveness_accounts_len,
+        propagate_item_class_data_fields_to_item_data, realloc_with_rent, resize_item_class,
+        sighash, spl_token_burn, spl_token_mint_to, spl_token_transfer, transfer_mint_authority,
+        update_item_class_with_inherited_information, verify, verify_and_affect_item_state_update,
+        verify_component, verify_cooldown, write_data, AddItemClassChildArgs,
+        AssertItemClassMintArgs, AssertPermissivenessAccessArgs, GetItemUsageArgs,
+        ItemClassDataReader, ReallocWithRentParams, ResizeItemClassArgs, TokenBurnParams,
+        TokenTransferParams, TransferMintAuthorityArgs, VerifyAndAffectItemStateUpdateArgs,
+        VerifyComponentArgs, VerifyCooldownArgs,
     },
     anchor_lang::{
         prelude::*,
-        solana_program::{instruction::Instruction, program::invoke, program_option::COption},
+        solana_program::{instruction::Instruction, program::invoke},
         AnchorDeserialize, AnchorSerialize, Discriminator,
     },
     anchor_spl::token::{Mint, Token, TokenAccount},
     arrayref::array_ref,
     std::str::FromStr,
 };
-anchor_lang::declare_id!("itemX1XWs9dK8T2Zca4vEEPfCAhRc7yvYFntPjTTVx6");
-pub const PREFIX: &str = "item";
+anchor_lang::declare_id!(raindrops_common::ids::program_id(
+    raindrops_common::ids::ITEM_ID
+));
+pub const PREFIX: &str = raindrops_common::seeds::ITEM_PREFIX;
 pub const STAKING_COUNTER: &str = "staking";
 pub const MARKER: &str = "marker";
-pub const PLAYER_ID: &str = "p1exdMJcjVao65QdewkaZRUnU6VPSXhus9n2GzWfh98";
+pub const CHILDREN: &str = "children";
+/// How many child classes one propagate_item_class_inheritance call will update.
+pub const MAX_PROPAGATION_BATCH: usize = 4;
+/// Item class data written before layouts were versioned has no version byte and starts with
+/// the 0 or 1 Option tag of free_build, so versions start at 2 to never be mistaken for it.
+/// Items keep theirs in layout_version, which was always 1 before.
+pub const LEGACY_LAYOUT_VERSION: u8 = 1;
+pub const LAYOUT_VERSION: u8 = 2;
+pub use raindrops_common::ids::PLAYER_ID;
 pub const RENT_ID: &str = "SysvarRent111111111111111111111111111111111";
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -41,6 +54,24 @@ pub struct CreateItemClassArgs {
     pub item_class_data: ItemClassData,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CloneItemClassArgs {
+    pub class_index: u64,
+    pub source_class_index: u64,
+    pub source_parent_class_index: Option<u64>,
+    pub source_item_class_mint: Pubkey,
+    pub space: u64,
+    pub desired_namespace_array_size: u16,
+    pub update_permissiveness_to_use: Option<PermissivenessType>,
+    // For the source's parent, which the clone becomes a child of, if it has one
+    pub parent_class_index: Option<u64>,
+    pub parent_of_parent_class_index: Option<u64>,
+    pub parent_update_permissiveness_to_use: Option<PermissivenessType>,
+    pub store_mint: bool,
+    pub store_metadata_fields: bool,
+    pub patch: Option<ItemClassDataPatch>,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct DrainItemClassArgs {
     pub class_index: u64,
@@ -57,6 +88,23 @@ pub struct UpdateItemClassArgs {
     pub item_class_data: Option<ItemClassData>,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MigrateItemClassArgs {
+    pub class_index: u64,
+    pub item_class_mint: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MigrateItemArgs {
+    pub index: u64,
+    pub item_mint: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct GrowNamespaceArrayArgs {
+    pub additional_namespace_slots: u16,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct DrainItemArgs {
     pub index: u64,
@@ -299,34 +347,19 @@ pub mod item {
         let metadata = &ctx.accounts.metadata;
         let edition = &ctx.accounts.edition;
         let parent = &ctx.accounts.parent;
-        let ed = edition.to_account_info();
         msg!("1");
-        let edition_option = if edition.data_len() > 0 {
-            Some(&ed)
-        } else {
-            let mint_authority_info = &ctx.remaining_accounts[ctx.remaining_accounts.len() - 2];
-            let token_program_info = &ctx.remaining_accounts[ctx.remaining_accounts.len() - 1];
-            assert_keys_equal(*token_program_info.key, spl_token::id())?;
-            assert_mint_authority_matches_mint(&item_mint.mint_authority, mint_authority_info)?;
-            if item_mint.mint_authority != COption::Some(item_class.key()) {
-                transfer_mint_authority(TransferMintAuthorityArgs {
-                    item_class_key: &item_class.key(),
-                    item_class_info: &item_class_info,
-                    mint_authority_info,
-                    token_program_info,
-                    mint: item_mint,
-                })?;
-            }
-            None
-        };
-        msg!("assert_valid_item_settings_for_edition_type");
-        assert_valid_item_settings_for_edition_type(edition_option, &item_class_data)?;
-        msg!("assert_metadata_valid");
-        assert_metadata_valid(
-            &metadata.to_account_info(),
-            edition_option,
-            &item_mint.key(),
-        )?;
+        assert_item_class_mint(AssertItemClassMintArgs {
+            item_class_info: &item_class_info,
+            item_mint,
+            metadata: &metadata.to_account_info(),
+            edition: &edition.to_account_info(),
+            item_class_data: &item_class_data,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        if let Some(c) = &mut item_class_data.category {
+            c.inherited = InheritanceState::NotInherited;
+        }
+
         msg!("2");
         let parent_info = parent.to_account_info();
         if !parent.data_is_empty()
@@ -341,16 +374,26 @@ pub mod item {
                 given_account: parent,
                 remaining_accounts: ctx.remaining_accounts,
                 permissiveness_to_use: &update_permissiveness_to_use,
-                permissiveness_array: &parent_deserialized
-                    .item_class_data(parent_info.data.borrow())?
-                    .settings
-                    .update_permissiveness,
+                permissiveness_array: &ItemClassDataReader::new(
+                    &parent_deserialized,
+                    parent_info.data.borrow(),
+                )?
+                .update_permissiveness()?,
                 class_index: parent_of_parent_class_index,
                 index: parent_class_index.unwrap(),
                 account_mint: None,
             })?;
 
             item_class.parent = Some(parent.key());
+            add_item_class_child(AddItemClassChildArgs {
+                parent: &parent_info,
+                parent_children: &ctx.accounts.parent_children.to_account_info(),
+                bump: *ctx.bumps.get("parent_children").unwrap(),
+                child: item_class.key(),
+                payer: &ctx.accounts.payer,
+                system_program: &ctx.accounts.system_program,
+                rent: &ctx.accounts.rent,
+            })?;
 
             parent_deserialized.existing_children = parent_deserialized
                 .existing_children
@@ -385,7 +428,7 @@ pub mod item {
         item_class.bump = *ctx.bumps.get("item_class").unwrap();
         if store_metadata_fields {
             item_class.metadata = Some(metadata.key());
-            item_class.edition = if edition.data_is_empty() {
+            item_class.edition = if !edition.data_is_empty() {
                 Some(edition.key())
             } else {
                 None
@@ -420,6 +463,255 @@ pub mod item {
         Ok(())
     }
 
+    /// Creates an item class for a new mint from the data of an existing one, with the patch
+    /// laid over it, so the whole config doesn't have to fit in the transaction. Gated by
+    /// the source's update permissiveness, and like create_item_class by the parent's update
+    /// permissiveness or the new mint's update authority. The clone gets the source's parent,
+    /// if it has one, and is registered as its child.
+    pub fn clone_item_class<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, CloneItemClass<'info>>,
+        args: CloneItemClassArgs,
+    ) -> Result<()> {
+        let CloneItemClassArgs {
+            class_index,
+            source_class_index,
+            source_parent_class_index,
+            source_item_class_mint,
+            desired_namespace_array_size,
+            update_permissiveness_to_use,
+            parent_class_index,
+            parent_of_parent_class_index,
+            parent_update_permissiveness_to_use,
+            store_mint,
+            store_metadata_fields,
+            patch,
+            ..
+        } = args;
+
+        let item_class = &mut ctx.accounts.item_class;
+        let item_class_info = item_class.to_account_info();
+        let source_item_class = &ctx.accounts.source_item_class;
+        let source_item_class_info = source_item_class.to_account_info();
+        let item_mint = &ctx.accounts.item_mint;
+        let metadata = &ctx.accounts.metadata;
+        let edition = &ctx.accounts.edition;
+        let parent = &ctx.accounts.parent;
+
+        let mut item_class_data =
+            source_item_class.item_class_data(source_item_class_info.data.borrow())?;
+
+        assert_permissiveness_access(AssertPermissivenessAccessArgs {
+            program_id: ctx.program_id,
+            given_account: &source_item_class_info,
+            remaining_accounts: ctx.remaining_accounts,
+            permissiveness_to_use: &update_permissiveness_to_use,
+            permissiveness_array: &item_class_data.settings.update_permissiveness,
+            index: source_class_index,
+            class_index: source_parent_class_index,
+            account_mint: Some(&source_item_class_mint),
+        })?;
+        // The accounts for the clone's own check follow the source's.
+        let clone_remaining_accounts = &ctx.remaining_accounts[permissiveness_accounts_len(
+            &update_permissiveness_to_use,
+            &item_class_data.settings.update_permissiveness,
+            Some(&source_item_class_mint),
+        )..];
+
+        if let Some(patch) = patch {
+            apply_item_class_data_patch(&mut item_class_data, patch);
+        }
+
+        assert_item_class_mint(AssertItemClassMintArgs {
+            item_class_info: &item_class_info,
+            item_mint,
+            metadata: &metadata.to_account_info(),
+            edition: &edition.to_account_info(),
+            item_class_data: &item_class_data,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        if let Some(source_parent) = source_item_class.parent {
+            assert_keys_equal(parent.key(), source_parent)?;
+            let parent_info = parent.to_account_info();
+            let mut parent_deserialized: Account<'_, ItemClass> = Account::try_from(&parent_info)?;
+
+            assert_permissiveness_access(AssertPermissivenessAccessArgs {
+                program_id: ctx.program_id,
+                given_account: &parent_info,
+                remaining_accounts: clone_remaining_accounts,
+                permissiveness_to_use: &parent_update_permissiveness_to_use,
+                permissiveness_array: &ItemClassDataReader::new(
+                    &parent_deserialized,
+                    parent_info.data.borrow(),
+                )?
+                .update_permissiveness()?,
+                class_index: parent_of_parent_class_index,
+                index: parent_class_index.unwrap(),
+                account_mint: None,
+            })?;
+
+            item_class.parent = Some(parent.key());
+            add_item_class_child(AddItemClassChildArgs {
+                parent: &parent_info,
+                parent_children: &ctx.accounts.parent_children.to_account_info(),
+                bump: *ctx.bumps.get("parent_children").unwrap(),
+                child: item_class.key(),
+                payer: &ctx.accounts.payer,
+                system_program: &ctx.accounts.system_program,
+                rent: &ctx.accounts.rent,
+            })?;
+
+            parent_deserialized.existing_children = parent_deserialized
+                .existing_children
+                .checked_add(1)
+                .ok_or(ErrorCode::NumericalOverflowError)?;
+            let parent_item_class_data =
+                parent_deserialized.item_class_data(parent_info.data.borrow())?;
+            update_item_class_with_inherited_information(
+                item_class,
+                &mut item_class_data,
+                &parent_deserialized,
+                &parent_item_class_data,
+            );
+            parent_deserialized.exit(ctx.program_id)?;
+        } else {
+            assert_keys_equal(parent.key(), item_class.key())?;
+            assert_permissiveness_access(AssertPermissivenessAccessArgs {
+                program_id: ctx.program_id,
+                given_account: &item_class_info,
+                remaining_accounts: clone_remaining_accounts,
+                permissiveness_to_use: &Some(PermissivenessType::UpdateAuthority),
+                permissiveness_array: &Some(vec![Permissiveness {
+                    permissiveness_type: PermissivenessType::UpdateAuthority,
+                    inherited: InheritanceState::NotInherited,
+                }]),
+                class_index: None,
+                index: class_index,
+                account_mint: Some(&item_mint.key()),
+            })?;
+        }
+
+        item_class.bump = *ctx.bumps.get("item_class").unwrap();
+        if store_metadata_fields {
+            item_class.metadata = Some(metadata.key());
+            item_class.edition = if !edition.data_is_empty() {
+                Some(edition.key())
+            } else {
+                None
+            }
+        }
+
+        if store_mint {
+            item_class.mint = Some(item_mint.key());
+        }
+
+        if desired_namespace_array_size > 0 {
+            let mut namespace_arr = vec![];
+
+            for _n in 0..desired_namespace_array_size {
+                namespace_arr.push(NamespaceAndIndex {
+                    namespace: anchor_lang::solana_program::system_program::id(),
+                    indexed: false,
+                    inherited: InheritanceState::NotInherited,
+                });
+            }
+
+            item_class.namespaces = Some(namespace_arr);
+        } else {
+            item_class.namespaces = None
+        }
+
+        resize_item_class(ResizeItemClassArgs {
+            item_class,
+            item_class_data: &item_class_data,
+            payer: &ctx.accounts.payer.to_account_info(),
+            system_program: &ctx.accounts.system_program.to_account_info(),
+            rent: &ctx.accounts.rent.to_account_info(),
+            allow_shrink: false,
+        })?;
+
+        write_data(item_class, &item_class_data)?;
+
+        Ok(())
+    }
+
+    /// Adds a class made before child classes were tracked to its parent's children, so
+    /// propagation can reach it. Anybody can call this.
+    pub fn register_item_class_child<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, RegisterItemClassChild<'info>>,
+    ) -> Result<()> {
+        let parent = &ctx.accounts.parent;
+        let item_class = &ctx.accounts.item_class;
+
+        require!(item_class.parent == Some(parent.key()), MustBeChild);
+
+        add_item_class_child(AddItemClassChildArgs {
+            parent: &parent.to_account_info(),
+            parent_children: &ctx.accounts.parent_children.to_account_info(),
+            bump: *ctx.bumps.get("parent_children").unwrap(),
+            child: item_class.key(),
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })
+    }
+
+    /// Permissionless crank that pushes a class's inheritable data down to a batch of its
+    /// children, passed writable in remaining accounts. Run it on a class, then on each of
+    /// its children, and so on, and the whole tree ends up matching the root. Children that
+    /// have been drained since they were registered are dropped from the list.
+    pub fn propagate_item_class_inheritance<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, PropagateItemClassInheritance<'info>>,
+    ) -> Result<()> {
+        let parent = &ctx.accounts.parent;
+        let parent_children = &mut ctx.accounts.parent_children;
+
+        require!(
+            ctx.remaining_accounts.len() <= MAX_PROPAGATION_BATCH,
+            PropagationBatchTooLarge
+        );
+
+        let parent_info = parent.to_account_info();
+        let parent_item_class_data = parent.item_class_data(parent_info.data.borrow())?;
+
+        for child_info in ctx.remaining_accounts {
+            let position = parent_children
+                .children
+                .iter()
+                .position(|child| child == child_info.key)
+                .ok_or(ErrorCode::NotARegisteredChild)?;
+
+            if child_info.data_is_empty() || child_info.owner != ctx.program_id {
+                parent_children.children.remove(position);
+                continue;
+            }
+
+            let mut child: Account<'_, ItemClass> = Account::try_from(child_info)?;
+            require!(child.parent == Some(parent.key()), MustBeChild);
+
+            let mut child_item_class_data = child.item_class_data(child_info.data.borrow())?;
+            update_item_class_with_inherited_information(
+                &mut child,
+                &mut child_item_class_data,
+                parent,
+                &parent_item_class_data,
+            );
+            // Inherited data can be bigger than what the child had room for.
+            resize_item_class(ResizeItemClassArgs {
+                item_class: &child,
+                item_class_data: &child_item_class_data,
+                payer: &ctx.accounts.payer.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+                rent: &ctx.accounts.rent.to_account_info(),
+                allow_shrink: false,
+            })?;
+            write_data(&mut child, &child_item_class_data)?;
+            child.exit(ctx.program_id)?;
+        }
+
+        Ok(())
+    }
+
     pub fn update_item_class<'a, 'b, 'c, 'info>(
         ctx: Context<'a, 'b, 'c, 'info, UpdateItemClass<'info>>,
         args: UpdateItemClassArgs,
@@ -440,7 +732,8 @@ pub mod item {
             item_class.item_class_data(item_class.to_account_info().data.borrow())?;
 
         msg!("assert_permissiveness_access check");
-        let mut new_item_class_data = if let Some(icd) = item_class_data {
+        let mut permission_checked = false;
+        let mut new_item_class_data = if let Some(mut icd) = item_class_data {
             if !ctx.remaining_accounts.is_empty() {
                 assert_permissiveness_access(AssertPermissivenessAccessArgs {
                     program_id: ctx.program_id,
@@ -452,6 +745,11 @@ pub mod item {
                     class_index: parent_class_index,
                     account_mint: Some(&item_mint.key()),
                 })?;
+                permission_checked = true;
+            }
+
+            if let Some(c) = &mut icd.category {
+                c.inherited = InheritanceState::NotInherited;
             }
 
             icd
@@ -479,10 +777,47 @@ pub mod item {
             return Err(error!(ErrorCode::ExpectedParent));
         }
 
+        // Permissionless inheritance updates only ever grow the account, otherwise anybody
+        // could collect the rent freed up by a shrink.
+        resize_item_class(ResizeItemClassArgs {
+            item_class,
+            item_class_data: &new_item_class_data,
+            payer: &ctx.accounts.payer.to_account_info(),
+            system_program: &ctx.accounts.system_program.to_account_info(),
+            rent: &ctx.accounts.rent.to_account_info(),
+            allow_shrink: permission_checked,
+        })?;
+
         write_data(item_class, &new_item_class_data)?;
         Ok(())
     }
 
+    /// Rewrites an item class's data in the current layout, growing the account if it has
+    /// no room for it. Anybody can call this, the payer covers the extra rent.
+    pub fn migrate_item_class<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, MigrateItemClass<'info>>,
+        _args: MigrateItemClassArgs,
+    ) -> Result<()> {
+        let item_class = &mut ctx.accounts.item_class;
+        let item_class_info = item_class.to_account_info();
+
+        if item_class.layout_version(item_class_info.data.borrow())? == LAYOUT_VERSION {
+            return Ok(());
+        }
+
+        let item_class_data = item_class.item_class_data(item_class_info.data.borrow())?;
+        resize_item_class(ResizeItemClassArgs {
+            item_class,
+            item_class_data: &item_class_data,
+            payer: &ctx.accounts.payer.to_account_info(),
+            system_program: &ctx.accounts.system_program.to_account_info(),
+            rent: &ctx.accounts.rent.to_account_info(),
+            allow_shrink: false,
+        })?;
+
+        write_data(item_class, &item_class_data)
+    }
+
     pub fn drain_item_class<'a, 'b, 'c, 'info>(
         ctx: Context<'a, 'b, 'c, 'info, DrainItemClass<'info>>,
         args: DrainItemClassArgs,
@@ -502,10 +837,11 @@ pub mod item {
             given_account: &item_class.to_account_info(),
             remaining_accounts: ctx.remaining_accounts,
             permissiveness_to_use: &update_permissiveness_to_use,
-            permissiveness_array: &item_class
-                .item_class_data(item_class.to_account_info().data.borrow())?
-                .settings
-                .update_permissiveness,
+            permissiveness_array: &ItemClassDataReader::new(
+                item_class,
+                item_class.to_account_info().data.borrow(),
+            )?
+            .update_permissiveness()?,
             index: class_index,
             class_index: parent_class_index,
             account_mint: Some(&item_class_mint.key()),
@@ -557,10 +893,11 @@ pub mod item {
             given_account: &item_class.to_account_info(),
             remaining_accounts: ctx.remaining_accounts,
             permissiveness_to_use: &update_permissiveness_to_use,
-            permissiveness_array: &item_class
-                .item_class_data(item_class.to_account_info().data.borrow())?
-                .settings
-                .update_permissiveness,
+            permissiveness_array: &ItemClassDataReader::new(
+                item_class,
+                item_class.to_account_info().data.borrow(),
+            )?
+            .update_permissiveness()?,
             index,
             class_index: Some(class_index),
             account_mint: Some(&item_mint.key()),
@@ -602,7 +939,14 @@ pub mod item {
         let ed = new_item_edition.to_account_info();
         let item_class_info = item_class.to_account_info();
         msg!("item_class_data");
-        let item_class_data = item_class.item_class_data(item_class_info.data.borrow())?;
+        let (builder_must_be_holder, children_must_be_editions, build_permissiveness) = {
+            let reader = ItemClassDataReader::new(item_class, item_class_info.data.borrow())?;
+            (
+                reader.builder_must_be_holder()?,
+                reader.children_must_be_editions()?,
+                reader.build_permissiveness()?,
+            )
+        };
         msg!("create_item_escrow_args");
         let CreateItemEscrowArgs {
             class_index,
@@ -617,8 +961,12 @@ pub mod item {
         if amount_to_make == 0 {
             return Err(error!(ErrorCode::CannotMakeZero));
         }
+        raindrops_namespace::utils::assert_not_frozen_in_namespaces(
+            &item_class_info,
+            ctx.remaining_accounts,
+        )?;
         msg!("assert_builder_is_holder");
-        assert_builder_must_be_holder_check(&item_class_data, new_item_token_holder)?;
+        assert_builder_must_be_holder_check(&builder_must_be_holder, new_item_token_holder)?;
 
         assert_is_ata(
             &new_item_token.to_account_info(),
@@ -633,7 +981,7 @@ pub mod item {
                 return Err(error!(ErrorCode::InsufficientBalance));
             }
             msg!("edition children_must_be_editions");
-            if let Some(c) = item_class_data.settings.children_must_be_editions {
+            if let Some(c) = children_must_be_editions {
                 if c.boolean {
                     let mut borrowed_data = ed.data.borrow_mut();
                     let data: &mut [u8] = *borrowed_data;
@@ -692,7 +1040,7 @@ pub mod item {
             given_account: &item_class.to_account_info(),
             remaining_accounts: ctx.remaining_accounts,
             permissiveness_to_use: &build_permissiveness_to_use,
-            permissiveness_array: &item_class_data.settings.build_permissiveness,
+            permissiveness_array: &build_permissiveness,
             index: class_index,
             class_index: parent_class_index,
             account_mint: Some(&item_class_mint),
@@ -734,22 +1082,33 @@ pub mod item {
             ..
         } = args;
 
+        raindrops_namespace::utils::assert_not_frozen_in_namespaces(
+            &craft_item.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
+
         let mut craft_item_counter = match ctx.accounts.craft_item_counter.load_mut() {
             Ok(val) => val,
             Err(_) => ctx.accounts.craft_item_counter.load_init()?,
         };
 
-        let item_class_data =
-            item_class.item_class_data(item_class.to_account_info().data.borrow())?;
+        let item_class_info = item_class.to_account_info();
+        let (builder_must_be_holder, build_permissiveness) = {
+            let reader = ItemClassDataReader::new(item_class, item_class_info.data.borrow())?;
+            (
+                reader.builder_must_be_holder()?,
+                reader.build_permissiveness()?,
+            )
+        };
 
-        assert_builder_must_be_holder_check(&item_class_data, new_item_token_holder)?;
+        assert_builder_must_be_holder_check(&builder_must_be_holder, new_item_token_holder)?;
 
         assert_permissiveness_access(AssertPermissivenessAccessArgs {
             program_id: ctx.program_id,
             given_account: &item_class.to_account_info(),
             remaining_accounts: ctx.remaining_accounts,
             permissiveness_to_use: &build_permissiveness_to_use,
-            permissiveness_array: &item_class_data.settings.build_permissiveness,
+            permissiveness_array: &build_permissiveness,
             index: class_index,
             class_index: parent_class_index,
             account_mint: Some(&item_class_mint),
@@ -885,17 +1244,23 @@ pub mod item {
             ..
         } = args;
 
-        let item_class_data =
-            item_class.item_class_data(item_class.to_account_info().data.borrow())?;
+        let item_class_info = item_class.to_account_info();
+        let (builder_must_be_holder, build_permissiveness) = {
+            let reader = ItemClassDataReader::new(item_class, item_class_info.data.borrow())?;
+            (
+                reader.builder_must_be_holder()?,
+                reader.build_permissiveness()?,
+            )
+        };
 
-        assert_builder_must_be_holder_check(&item_class_data, new_item_token_holder)?;
+        assert_builder_must_be_holder_check(&builder_must_be_holder, new_item_token_holder)?;
         msg!("assert_permissiveness_access");
         assert_permissiveness_access(AssertPermissivenessAccessArgs {
             program_id: ctx.program_id,
             given_account: &item_class.to_account_info(),
             remaining_accounts: ctx.remaining_accounts,
             permissiveness_to_use: &build_permissiveness_to_use,
-            permissiveness_array: &item_class_data.settings.build_permissiveness,
+            permissiveness_array: &build_permissiveness,
             index: class_index,
             class_index: parent_class_index,
             account_mint: Some(&item_class_mint),
@@ -1002,17 +1367,20 @@ pub mod item {
             ..
         } = args;
 
-        let item_class_data =
-            item_class.item_class_data(item_class.to_account_info().data.borrow())?;
+        let item_class_info = item_class.to_account_info();
+        let reader = ItemClassDataReader::new(item_class, item_class_info.data.borrow())?;
 
-        assert_builder_must_be_holder_check(&item_class_data, new_item_token_holder)?;
+        assert_builder_must_be_holder_check(
+            &reader.builder_must_be_holder()?,
+            new_item_token_holder,
+        )?;
 
         assert_permissiveness_access(AssertPermissivenessAccessArgs {
             program_id: ctx.program_id,
             given_account: &item_class.to_account_info(),
             remaining_accounts: ctx.remaining_accounts,
             permissiveness_to_use: &build_permissiveness_to_use,
-            permissiveness_array: &item_class_data.settings.build_permissiveness,
+            permissiveness_array: &reader.build_permissiveness()?,
             index: class_index,
             class_index: parent_class_index,
             account_mint: Some(&item_class_mint),
@@ -1022,16 +1390,9 @@ pub mod item {
 
         require!(item_escrow.build_began.is_none(), BuildPhaseAlreadyStarted);
 
-        if let Some(components) = &item_class_data.config.components {
-            let mut counter = 0;
-            for c in components {
-                if c.component_scope == component_scope {
-                    counter += 1;
-                }
-            }
-
+        if let Some(counter) = reader.component_count(&component_scope)? {
             if counter == 0 {
-                if let Some(c) = item_class_data.settings.free_build {
+                if let Some(c) = reader.free_build()? {
                     if !c.boolean {
                         return Err(error!(ErrorCode::MustUseRealScope));
                     }
@@ -1039,11 +1400,11 @@ pub mod item {
             } else {
                 require!(counter == item_escrow.step as usize, StillMissingComponents);
             }
-        } else if let Some(component_root) = &item_class_data.config.component_root {
+        } else if let Some(component_root) = reader.component_root()? {
             if let Some(en_proof) = end_node_proof {
                 if let Some(total_s) = total_steps {
                     if total_s == 0 {
-                        if let Some(c) = item_class_data.settings.free_build {
+                        if let Some(c) = reader.free_build()? {
                             if !c.boolean {
                                 return Err(error!(ErrorCode::MustUseRealScope));
                             }
@@ -1068,7 +1429,7 @@ pub mod item {
             } else {
                 return Err(error!(ErrorCode::MissingMerkleInfo));
             }
-        } else if let Some(c) = item_class_data.settings.free_build {
+        } else if let Some(c) = reader.free_build()? {
             if !c.boolean {
                 return Err(error!(ErrorCode::MustUseMerkleOrComponentList));
             }
@@ -1136,11 +1497,17 @@ pub mod item {
             &new_item_mint.key(),
         )?;
 
-        let item_class_data =
-            item_class.item_class_data(item_class.to_account_info().data.borrow())?;
+        let item_class_info = item_class.to_account_info();
+        let (builder_must_be_holder, build_permissiveness) = {
+            let reader = ItemClassDataReader::new(item_class, item_class_info.data.borrow())?;
+            (
+                reader.builder_must_be_holder()?,
+                reader.build_permissiveness()?,
+            )
+        };
 
         msg!("assert_builder_must_be_holder_check");
-        assert_builder_must_be_holder_check(&item_class_data, new_item_token_holder)?;
+        assert_builder_must_be_holder_check(&builder_must_be_holder, new_item_token_holder)?;
 
         msg!("assert_permissiveness_access");
         assert_permissiveness_access(AssertPermissivenessAccessArgs {
@@ -1148,7 +1515,7 @@ pub mod item {
             given_account: &item_class.to_account_info(),
             remaining_accounts: ctx.remaining_accounts,
             permissiveness_to_use: &build_permissiveness_to_use,
-            permissiveness_array: &item_class_data.settings.build_permissiveness,
+            permissiveness_array: &build_permissiveness,
             index: class_index,
             class_index: parent_class_index,
             account_mint: Some(&item_class_mint),
@@ -1175,7 +1542,7 @@ pub mod item {
         }
 
         new_item.bump = *ctx.bumps.get("new_item").unwrap();
-        new_item.padding = 1;
+        new_item.layout_version = LAYOUT_VERSION;
         new_item.class_index = class_index;
         new_item.parent = item_class.key();
 
@@ -1189,7 +1556,11 @@ pub mod item {
         }
 
         msg!("propagate_item_class_data_fields_to_item_data");
-        propagate_item_class_data_fields_to_item_data(new_item, item_class, &item_class_data);
+        propagate_item_class_data_fields_to_item_data(
+            new_item,
+            item_class,
+            &ItemClassDataReader::new(item_class, item_class_info.data.borrow())?,
+        )?;
 
         if new_item_mint.supply <= 1 {
             item_class.existing_children = item_class
@@ -1223,6 +1594,16 @@ pub mod item {
 
         item_escrow.deactivated = true;
 
+        // The new item is not written out yet, so the craft counts for the class's namespaces.
+        raindrops_namespace::utils::record_stat_in_namespaces(
+            raindrops_namespace::utils::RecordStatInNamespacesArgs {
+                artifact: &item_class.to_account_info(),
+                stat: raindrops_namespace::NamespaceStat::CraftCompleted,
+                program_id: ctx.program_id,
+                remaining_accounts: ctx.remaining_accounts,
+            },
+        )?;
+
         Ok(())
     }
 
@@ -1233,14 +1614,67 @@ pub mod item {
         let item_class = &mut ctx.accounts.item_class;
         let item = &mut ctx.accounts.item;
 
-        let item_class_data =
-            item_class.item_class_data(item_class.to_account_info().data.borrow())?;
+        let item_class_info = item_class.to_account_info();
+        propagate_item_class_data_fields_to_item_data(
+            item,
+            item_class,
+            &ItemClassDataReader::new(item_class, item_class_info.data.borrow())?,
+        )?;
 
-        propagate_item_class_data_fields_to_item_data(item, item_class, &item_class_data);
+        Ok(())
+    }
+
+    /// Brings an item up to the current layout, growing the account if need be. Anybody
+    /// can call this, the payer covers the extra rent.
+    pub fn migrate_item<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, MigrateItem<'info>>,
+        _args: MigrateItemArgs,
+    ) -> Result<()> {
+        let item = &mut ctx.accounts.item;
+
+        if item.layout_version >= LAYOUT_VERSION {
+            return Ok(());
+        }
+
+        // Legacy items already have the current layout, only the version changes.
+        item.layout_version = LAYOUT_VERSION;
+
+        let item_info = item.to_account_info();
+        let needed = 8 + AnchorSerialize::try_to_vec(&**item)?.len();
+        if needed > item_info.data_len() {
+            realloc_with_rent(ReallocWithRentParams {
+                account: &item_info,
+                payer: &ctx.accounts.payer.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+                rent: &ctx.accounts.rent.to_account_info(),
+                new_len: needed,
+            })?;
+        }
 
         Ok(())
     }
 
+    /// Adds empty namespace slots to an item class, item or item escrow. Only this program
+    /// can realloc them, the namespace program's grow_namespace_array forwards here.
+    /// Anybody can call this, the payer covers the extra rent.
+    pub fn grow_namespace_array<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, GrowNamespaceArray<'info>>,
+        args: GrowNamespaceArrayArgs,
+    ) -> Result<()> {
+        let artifact = &ctx.accounts.artifact.to_account_info();
+        assert_owned_by(artifact, ctx.program_id)?;
+
+        raindrops_namespace::utils::grow_namespace_array(
+            raindrops_namespace::utils::GrowNamespaceArrayParams {
+                artifact,
+                payer: &ctx.accounts.payer.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+                rent: &ctx.accounts.rent.to_account_info(),
+                additional_slots: args.additional_namespace_slots as usize,
+            },
+        )
+    }
+
     pub fn deactivate_item_escrow<'a, 'b, 'c, 'info>(
         ctx: Context<'a, 'b, 'c, 'info, DeactivateItemEscrow<'info>>,
         _args: DeactivateItemEscrowArgs,
@@ -1314,6 +1748,10 @@ pub mod item {
 
         require!(amount > 0, MustBeGreaterThanZero);
         require!(item_account.amount >= amount, InsufficientBalance);
+        raindrops_namespace::utils::assert_not_frozen_in_namespaces(
+            &item.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
 
         item_activation_marker.bump = *ctx.bumps.get("item_activation_marker").unwrap();
 
@@ -1335,7 +1773,7 @@ pub mod item {
             let item_class_info = item_class.to_account_info();
             let item_info = item.to_account_info();
             let item_account_info = item_account.to_account_info();
-            let accounts = vec![
+            let mut accounts = vec![
                 item_class_info,
                 item_info,
                 item_account_info,
@@ -1343,12 +1781,18 @@ pub mod item {
             ];
             assert_keys_equal(validation_program.key(), validation.key)?;
 
-            let keys = vec![
+            let mut keys = vec![
                 AccountMeta::new_readonly(item_class.key(), false),
                 AccountMeta::new_readonly(item.key(), false),
                 AccountMeta::new_readonly(item_account.key(), false),
             ];
 
+            // Validators may need more accounts than the item itself, so hand them everything extra.
+            for acct in ctx.remaining_accounts {
+                keys.push(AccountMeta::new_readonly(acct.key(), false));
+                accounts.push(acct.clone());
+            }
+
             invoke(
                 &Instruction {
                     program_id: validation.key,
@@ -1408,6 +1852,15 @@ pub mod item {
             }
         };
 
+        raindrops_namespace::utils::record_stat_in_namespaces(
+            raindrops_namespace::utils::RecordStatInNamespacesArgs {
+                artifact: &item.to_account_info(),
+                stat: raindrops_namespace::NamespaceStat::ItemActivated,
+                program_id: ctx.program_id,
+                remaining_accounts: ctx.remaining_accounts,
+            },
+        )?;
+
         Ok(())
     }
 
@@ -1713,6 +2166,10 @@ pub struct CreateItemClass<'info> {
     ///CHECK: TODO
     #[account(mut)]
     parent: UncheckedAccount<'info>,
+    // The parent's list of child classes, created on its first child. Unused without a parent.
+    ///CHECK: created or added to in add_item_class_child
+    #[account(mut, seeds=[PREFIX.as_bytes(), parent.key().as_ref(), CHILDREN.as_bytes()], bump)]
+    parent_children: UncheckedAccount<'info>,
     #[account(mut)]
     payer: Signer<'info>,
     system_program: Program<'info, System>,
@@ -2152,9 +2609,135 @@ pub struct UpdateItemClass<'info> {
     item_mint: Account<'info, Mint>,
     // Pass up system if you dont have a parent
     parent: UncheckedAccount<'info>,
+    // Pays for the account growing with the data, and gets the rent back when it shrinks
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
     // See the [COMMON REMAINING ACCOUNTS] ctrl f for this
 }
 
+#[derive(Accounts)]
+#[instruction(args: CloneItemClassArgs)]
+pub struct CloneItemClass<'info> {
+    #[account(
+        init,
+        seeds=[PREFIX.as_bytes(), item_mint.key().as_ref(), &args.class_index.to_le_bytes()],
+        bump,
+        space=args.space as usize,
+        payer=payer,
+        constraint=args.space as usize >= MIN_ITEM_CLASS_SIZE
+    )]
+    item_class: Account<'info, ItemClass>,
+    item_mint: Account<'info, Mint>,
+    ///CHECK: TODO
+    metadata: UncheckedAccount<'info>,
+    ///CHECK: TODO
+    edition: UncheckedAccount<'info>,
+    #[account(
+        seeds=[
+            PREFIX.as_bytes(),
+            args.source_item_class_mint.as_ref(),
+            &args.source_class_index.to_le_bytes()
+        ],
+        bump=source_item_class.bump
+    )]
+    source_item_class: Account<'info, ItemClass>,
+    // The source's parent, or the new item class if it has none, as in create_item_class.
+    ///CHECK: checked against the source's parent or the new item class in clone_item_class
+    #[account(mut)]
+    parent: UncheckedAccount<'info>,
+    ///CHECK: created or added to in add_item_class_child
+    #[account(mut, seeds=[PREFIX.as_bytes(), parent.key().as_ref(), CHILDREN.as_bytes()], bump)]
+    parent_children: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+    // See the [COMMON REMAINING ACCOUNTS] ctrl f for this, checked against the source class.
+    // Then the same again for the parent's update permissiveness, or if the source has no
+    // parent, the new mint's:
+    // metadata_update_authority [signer]
+    // metadata [readable]
+    // If edition is not present you also need to pass up, last:
+    // mint_authority [signer] for minting authority to be handed over
+    // token program [readable]
+}
+
+#[derive(Accounts)]
+pub struct RegisterItemClassChild<'info> {
+    parent: Account<'info, ItemClass>,
+    item_class: Account<'info, ItemClass>,
+    ///CHECK: created or added to in add_item_class_child
+    #[account(mut, seeds=[PREFIX.as_bytes(), parent.key().as_ref(), CHILDREN.as_bytes()], bump)]
+    parent_children: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct PropagateItemClassInheritance<'info> {
+    parent: Account<'info, ItemClass>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), parent.key().as_ref(), CHILDREN.as_bytes()], bump=parent_children.bump)]
+    parent_children: Account<'info, ItemClassChildren>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+    // Up to MAX_PROPAGATION_BATCH child item classes [writable] in remaining accounts
+}
+
+#[derive(Accounts)]
+#[instruction(args: MigrateItemClassArgs)]
+pub struct MigrateItemClass<'info> {
+    #[account(
+        mut,
+        seeds=[
+            PREFIX.as_bytes(),
+            args.item_class_mint.as_ref(),
+            &args.class_index.to_le_bytes()
+        ],
+        bump=item_class.bump
+    )]
+    item_class: Account<'info, ItemClass>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: MigrateItemArgs)]
+pub struct MigrateItem<'info> {
+    #[account(
+        mut,
+        seeds=[
+            PREFIX.as_bytes(),
+            args.item_mint.as_ref(),
+            &args.index.to_le_bytes()
+        ],
+        bump=item.bump
+    )]
+    item: Account<'info, Item>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct GrowNamespaceArray<'info> {
+    ///CHECK: an item class, item or item escrow of this program, checked in grow_namespace_array
+    #[account(mut)]
+    artifact: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
 #[derive(Accounts)]
 #[instruction(args: UpdateItemArgs)]
 pub struct UpdateItem<'info> {
@@ -2592,6 +3175,7 @@ pub enum ChildUpdatePropagationPermissivenessType {
     StakingPermissiveness,
     Namespaces,
     FreeBuildPermissiveness,
+    Category,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
@@ -2609,6 +3193,12 @@ pub struct NamespaceAndIndex {
     pub inherited: InheritanceState,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ItemCategory {
+    pub category: String,
+    pub inherited: InheritanceState,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct DNPItem {
     pub key: Pubkey,
@@ -2633,6 +3223,7 @@ pub const MIN_ITEM_CLASS_SIZE: usize = 8 + // key
 4 + // roots
 2 + // staking durations
 8 + // existing children
+1 + // category
 1; //bump
 
 pub trait Inherited: Clone {
@@ -2640,6 +3231,15 @@ pub trait Inherited: Clone {
     fn get_inherited(&self) -> &InheritanceState;
 }
 
+impl Inherited for ItemCategory {
+    fn set_inherited(&mut self, i: InheritanceState) {
+        self.inherited = i;
+    }
+    fn get_inherited(&self) -> &InheritanceState {
+        &self.inherited
+    }
+}
+
 impl Inherited for Root {
     fn set_inherited(&mut self, i: InheritanceState) {
         self.inherited = i;
@@ -2754,10 +3354,26 @@ pub struct ItemClassConfig {
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct ItemClassData {
+    // Only stored from LAYOUT_VERSION on, legacy data has no category
+    pub category: Option<ItemCategory>,
     pub settings: ItemClassSettings,
     pub config: ItemClassConfig,
 }
 
+// Laid over a copied ItemClassData in clone_item_class, unset fields are left alone.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ItemClassDataPatch {
+    pub category: Option<ItemCategory>,
+    pub settings: Option<ItemClassSettings>,
+    pub usage_root: Option<Root>,
+    pub usage_state_root: Option<Root>,
+    pub component_root: Option<Root>,
+    // Each replaces the usage with the same index, or is added if there is none
+    pub usages: Option<Vec<ItemUsage>>,
+    // Replaces the whole list
+    pub components: Option<Vec<Component>>,
+}
+
 #[account]
 pub struct ItemClass {
     pub namespaces: Option<Vec<NamespaceAndIndex>>,
@@ -2773,6 +3389,20 @@ pub struct ItemClass {
     // item class data is after this, but we cant serialize or deserialize because it blows stacks
 }
 
+/// Keys of the item classes that have this class as their parent.
+/// seed ['item', item program, parent item class, 'children']
+#[account]
+pub struct ItemClassChildren {
+    pub parent: Pubkey,
+    pub bump: u8,
+    pub children: Vec<Pubkey>,
+}
+
+pub const MIN_ITEM_CLASS_CHILDREN_SIZE: usize = 8 + // key
+32 + // parent
+1 + // bump
+4; // number of children
+
 #[account]
 pub struct ItemEscrow {
     pub namespaces: Option<Vec<NamespaceAndIndex>>,
@@ -2857,8 +3487,9 @@ pub struct UsageInfo {
 #[account]
 pub struct Item {
     pub namespaces: Option<Vec<NamespaceAndIndex>>,
-    // extra byte that is always 1 to simulate same structure as item class.
-    pub padding: u8,
+    /// LEGACY_LAYOUT_VERSION for items made before layouts were versioned. Never 0, which
+    /// also keeps the parent where it is on an item class that has one.
+    pub layout_version: u8,
     pub parent: Pubkey,
     pub class_index: u64,
     pub mint: Option<Pubkey>,
@@ -2990,4 +3621,14 @@ pub enum ErrorCode {
     AtaShouldNotHaveDelegate,
     #[msg("Reinitialization hack detected")]
     ReinitializationDetected,
+    #[msg("Account cannot grow by that much in one instruction")]
+    AccountGrowthTooLarge,
+    #[msg("Too many children to propagate to in one go")]
+    PropagationBatchTooLarge,
+    #[msg("This item class is not in the parent's list of children")]
+    NotARegisteredChild,
+    #[msg("Account was written with a newer layout than this program knows")]
+    UnsupportedLayoutVersion,
+    #[msg("The mint authority and token program must be the last two remaining accounts")]
+    MintAuthorityAccountsMissing,
 }
@@ -1,46 +1,282 @@
 use {
     crate::{
-        ChildUpdatePropagationPermissivenessType, Component, CraftUsageInfo, ErrorCode,
-        InheritanceState, Inherited, Item, ItemActivationMarker, ItemActivationMarkerProofCounter,
-        ItemClass, ItemClassData, ItemClassType, ItemEscrow, ItemUsage, ItemUsageState,
-        ItemUsageType, Permissiveness, PermissivenessType, UsageInfo, PREFIX,
+        Boolean, ChildUpdatePropagationPermissiveness, ChildUpdatePropagationPermissivenessType,
+        Component, CraftUsageInfo, ErrorCode, InheritanceState, Inherited, Item,
+        ItemActivationMarker, ItemActivationMarkerProofCounter, ItemCategory, ItemClass,
+        ItemClassChildren, ItemClassData, ItemClassDataPatch, ItemClassType, ItemEscrow, ItemUsage,
+        ItemUsageState, ItemUsageType, Permissiveness, PermissivenessType, Root, UsageInfo,
+        CHILDREN, LAYOUT_VERSION, LEGACY_LAYOUT_VERSION, MIN_ITEM_CLASS_CHILDREN_SIZE, PREFIX,
     },
     anchor_lang::{
         error,
         prelude::{
             msg, Account, AccountInfo, AnchorDeserialize, AnchorSerialize, Program, ProgramError,
-            Pubkey, Rent, Result, SolanaSysvar, System, Sysvar, UncheckedAccount,
+            Pubkey, Rent, Result, Signer, SolanaSysvar, System, Sysvar, UncheckedAccount,
         },
         require,
         solana_program::{
+            entrypoint::MAX_PERMITTED_DATA_INCREASE,
             hash,
             program::{invoke, invoke_signed},
             program_option::COption,
             program_pack::{IsInitialized, Pack},
             system_instruction,
         },
-        Key, ToAccountInfo,
+        AccountsExit, Key, ToAccountInfo,
     },
     anchor_spl::token::{Mint, Token},
     arrayref::array_ref,
     spl_associated_token_account::get_associated_token_address,
     spl_token::instruction::{close_account, initialize_account2, set_authority, AuthorityType},
-    std::cell::RefCell,
+    std::cell::{Ref, RefCell},
     std::convert::TryInto,
 };
 
 impl ItemClass {
+    /// Layout of the item class data after this struct.
+    pub fn layout_version(&self, data: &RefCell<&mut [u8]>) -> Result<u8> {
+        let (ctr, _) = get_class_write_offsets(self, data);
+        read_layout_version(data.borrow()[ctr as usize])
+    }
+
+    /// Decodes the item class data whatever layout it was written in.
     pub fn item_class_data(&self, data: &RefCell<&mut [u8]>) -> Result<ItemClassData> {
         let (ctr, end_ctr) = get_class_write_offsets(self, data);
+        let layout_version = self.layout_version(data)?;
+        let data = data.borrow();
 
         //  msg!("Ctr {}->{} {:?}", ctr, end_ctr, &data.borrow());
-        let item_class_data: ItemClassData =
-            AnchorDeserialize::try_from_slice(&data.borrow()[ctr as usize..end_ctr as usize])?;
+        let item_class_data = match layout_version {
+            LEGACY_LAYOUT_VERSION => {
+                let (settings, config) =
+                    AnchorDeserialize::try_from_slice(&data[ctr as usize..end_ctr as usize])?;
+                ItemClassData {
+                    category: None,
+                    settings,
+                    config,
+                }
+            }
+            _ => AnchorDeserialize::try_from_slice(&data[ctr as usize + 1..end_ctr as usize])?,
+        };
 
         Ok(item_class_data)
     }
 }
 
+/// Legacy item class data has no version byte and starts with free_build's Option tag.
+fn read_layout_version(first_byte: u8) -> Result<u8> {
+    match first_byte {
+        0 | 1 => Ok(LEGACY_LAYOUT_VERSION),
+        LAYOUT_VERSION => Ok(LAYOUT_VERSION),
+        _ => Err(error!(ErrorCode::UnsupportedLayoutVersion)),
+    }
+}
+
+/// Reads single fields of the item class data without deserializing the rest. Everything
+/// before the field asked for is decoded one value at a time and dropped, so only one
+/// usage or component is ever held at once. Holds a borrow of the account data.
+pub struct ItemClassDataReader<'a, 'b> {
+    data: Ref<'a, &'b mut [u8]>,
+    start: usize,
+}
+
+impl<'a, 'b> ItemClassDataReader<'a, 'b> {
+    pub fn new(item_class: &ItemClass, data: &'a RefCell<&'b mut [u8]>) -> Result<Self> {
+        let data = data.borrow();
+        let mut start = get_class_data_offset(item_class);
+        if read_layout_version(data[start])? != LEGACY_LAYOUT_VERSION {
+            let mut buf: &[u8] = &data[start + 1..];
+            skip::<Option<ItemCategory>>(&mut buf)?;
+            start = data.len() - buf.len();
+        }
+        Ok(ItemClassDataReader { data, start })
+    }
+
+    fn at_builder_must_be_holder(&self) -> Result<&[u8]> {
+        let mut buf: &[u8] = &self.data[self.start..];
+        skip::<Option<Boolean>>(&mut buf)?; // free_build
+        skip::<Option<Boolean>>(&mut buf)?; // children_must_be_editions
+        Ok(buf)
+    }
+
+    fn at_update_permissiveness(&self) -> Result<&[u8]> {
+        let mut buf = self.at_builder_must_be_holder()?;
+        skip::<Option<Boolean>>(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn at_config(&self) -> Result<&[u8]> {
+        let mut buf = self.at_update_permissiveness()?;
+        skip_option_vec::<Permissiveness>(&mut buf)?; // update_permissiveness
+        skip_option_vec::<Permissiveness>(&mut buf)?; // build_permissiveness
+        skip::<Option<u64>>(&mut buf)?; // staking_warm_up_duration
+        skip::<Option<u64>>(&mut buf)?; // staking_cooldown_duration
+        skip_option_vec::<Permissiveness>(&mut buf)?; // staking_permissiveness
+        skip_option_vec::<Permissiveness>(&mut buf)?; // unstaking_permissiveness
+        skip_option_vec::<ChildUpdatePropagationPermissiveness>(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn at_usages(&self) -> Result<&[u8]> {
+        let mut buf = self.at_config()?;
+        skip::<Option<Root>>(&mut buf)?; // usage_root
+        skip::<Option<Root>>(&mut buf)?; // usage_state_root
+        skip::<Option<Root>>(&mut buf)?; // component_root
+        Ok(buf)
+    }
+
+    fn at_components(&self) -> Result<&[u8]> {
+        let mut buf = self.at_usages()?;
+        skip_option_vec::<ItemUsage>(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn free_build(&self) -> Result<Option<Boolean>> {
+        read(&mut &self.data[self.start..])
+    }
+
+    pub fn children_must_be_editions(&self) -> Result<Option<Boolean>> {
+        let mut buf: &[u8] = &self.data[self.start..];
+        skip::<Option<Boolean>>(&mut buf)?;
+        read(&mut buf)
+    }
+
+    pub fn builder_must_be_holder(&self) -> Result<Option<Boolean>> {
+        read(&mut self.at_builder_must_be_holder()?)
+    }
+
+    pub fn update_permissiveness(&self) -> Result<Option<Vec<Permissiveness>>> {
+        read(&mut self.at_update_permissiveness()?)
+    }
+
+    pub fn build_permissiveness(&self) -> Result<Option<Vec<Permissiveness>>> {
+        let mut buf = self.at_update_permissiveness()?;
+        skip_option_vec::<Permissiveness>(&mut buf)?;
+        read(&mut buf)
+    }
+
+    pub fn usage_root(&self) -> Result<Option<Root>> {
+        read(&mut self.at_config()?)
+    }
+
+    pub fn usage_state_root(&self) -> Result<Option<Root>> {
+        let mut buf = self.at_config()?;
+        skip::<Option<Root>>(&mut buf)?;
+        read(&mut buf)
+    }
+
+    pub fn component_root(&self) -> Result<Option<Root>> {
+        let mut buf = self.at_config()?;
+        skip::<Option<Root>>(&mut buf)?;
+        skip::<Option<Root>>(&mut buf)?;
+        read(&mut buf)
+    }
+
+    /// The listed usage with this index, if there is one.
+    pub fn item_usage(&self, usage_index: u16) -> Result<Option<ItemUsage>> {
+        let mut buf = self.at_usages()?;
+        for _ in 0..read_option_vec_len(&mut buf)? {
+            let usage: ItemUsage = read(&mut buf)?;
+            if usage.index == usage_index {
+                return Ok(Some(usage));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Indices of the listed usages, None if the class has no usage list.
+    pub fn usage_indices(&self) -> Result<Option<Vec<u16>>> {
+        let mut buf = self.at_usages()?;
+        if read::<u8>(&mut buf)? == 0 {
+            return Ok(None);
+        }
+        let mut indices = vec![];
+        for _ in 0..read::<u32>(&mut buf)? {
+            let usage: ItemUsage = read(&mut buf)?;
+            indices.push(usage.index);
+        }
+        Ok(Some(indices))
+    }
+
+    pub fn first_item_usage(&self) -> Result<Option<ItemUsage>> {
+        let mut buf = self.at_usages()?;
+        if read_option_vec_len(&mut buf)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(read(&mut buf)?))
+    }
+
+    /// The component for this step of the scope, the scope's components being built in the
+    /// order they are listed, along with how many components the scope has.
+    pub fn component(
+        &self,
+        component_scope: &str,
+        step: usize,
+    ) -> Result<(Option<Component>, usize)> {
+        let mut buf = self.at_components()?;
+        let mut chosen = None;
+        let mut counter: usize = 0;
+        for _ in 0..read_option_vec_len(&mut buf)? {
+            let component: Component = read(&mut buf)?;
+            if component.component_scope == component_scope {
+                if counter == step {
+                    chosen = Some(component);
+                }
+                counter += 1;
+            }
+        }
+        Ok((chosen, counter))
+    }
+
+    /// How many listed components the scope has, None if the class has no component list.
+    pub fn component_count(&self, component_scope: &str) -> Result<Option<usize>> {
+        let mut buf = self.at_components()?;
+        if read::<u8>(&mut buf)? == 0 {
+            return Ok(None);
+        }
+        let mut counter: usize = 0;
+        for _ in 0..read::<u32>(&mut buf)? {
+            let component: Component = read(&mut buf)?;
+            if component.component_scope == component_scope {
+                counter += 1;
+            }
+        }
+        Ok(Some(counter))
+    }
+
+    pub fn first_component(&self) -> Result<Option<Component>> {
+        let mut buf = self.at_components()?;
+        if read_option_vec_len(&mut buf)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(read(&mut buf)?))
+    }
+}
+
+fn read<T: AnchorDeserialize>(buf: &mut &[u8]) -> Result<T> {
+    Ok(T::deserialize(buf)?)
+}
+
+fn skip<T: AnchorDeserialize>(buf: &mut &[u8]) -> Result<()> {
+    read::<T>(buf)?;
+    Ok(())
+}
+
+/// Length of an Option<Vec<_>>, 0 if it is None.
+fn read_option_vec_len(buf: &mut &[u8]) -> Result<u32> {
+    match read::<u8>(buf)? {
+        0 => Ok(0),
+        _ => read::<u32>(buf),
+    }
+}
+
+fn skip_option_vec<T: AnchorDeserialize>(buf: &mut &[u8]) -> Result<()> {
+    for _ in 0..read_option_vec_len(buf)? {
+        skip::<T>(buf)?;
+    }
+    Ok(())
+}
+
 pub fn assert_initialized<T: Pack + IsInitialized>(account_info: &AccountInfo) -> Result<T> {
     let account: T = T::unpack_unchecked(&account_info.data.borrow())?;
     if !account.is_initialized() {
@@ -73,12 +309,9 @@ pub struct TokenTransferParams<'a: 'b, 'b> {
     pub token_program: AccountInfo<'a>,
 }
 
-pub fn get_class_write_offsets(
-    item_class: &ItemClass,
-    item_data: &RefCell<&mut [u8]>,
-) -> (u64, u64) {
+/// Where the item class data starts, right after the ItemClass struct.
+pub fn get_class_data_offset(item_class: &ItemClass) -> usize {
     let mut ctr: usize = 8;
-    let data = item_data.borrow();
     if let Some(ns) = &item_class.namespaces {
         ctr += 1 + 4 + 34 * ns.len();
     } else {
@@ -111,8 +344,29 @@ pub fn get_class_write_offsets(
 
     ctr += 9; // bump and existing childern (1 + 8)
 
+    ctr
+}
+
+pub fn get_class_write_offsets(
+    item_class: &ItemClass,
+    item_data: &RefCell<&mut [u8]>,
+) -> (u64, u64) {
+    let ctr = get_class_data_offset(item_class);
+    let data = item_data.borrow();
     let mut end_ctr = ctr;
 
+    // layout version and category, both absent on legacy data
+    if data[end_ctr] > 1 {
+        end_ctr += 1;
+
+        if data[end_ctr] == 1 {
+            let sub = &data[end_ctr + 1..end_ctr + 5];
+            end_ctr += 1 + 4 + u32::from_le_bytes([sub[0], sub[1], sub[2], sub[3]]) as usize + 1;
+        } else {
+            end_ctr += 1;
+        }
+    }
+
     // Item Class Settings
     // free_build
     if data[end_ctr] == 1 {
@@ -192,21 +446,21 @@ pub fn get_class_write_offsets(
     // ItemClassConfig
     // usage_root
     if data[end_ctr] == 1 {
-        end_ctr += 33;
+        end_ctr += 34;
     } else {
         end_ctr += 1;
     }
 
     // usage_state_root
     if data[end_ctr] == 1 {
-        end_ctr += 33;
+        end_ctr += 34;
     } else {
         end_ctr += 1;
     }
 
     // component_root
     if data[end_ctr] == 1 {
-        end_ctr += 33;
+        end_ctr += 34;
     } else {
         end_ctr += 1;
     }
@@ -295,7 +549,7 @@ pub fn get_class_write_offsets(
                 // wearable
                 let sub = &data[end_ctr + 1..end_ctr + 5];
                 let num_of_body_parts = u32::from_le_bytes([sub[0], sub[1], sub[2], sub[3]]);
-                end_ctr += 4;
+                end_ctr += 5;
                 for _ in 0..num_of_body_parts {
                     // body part string
                     let sub = &data[end_ctr..end_ctr + 4];
@@ -406,6 +660,8 @@ pub fn get_class_write_offsets(
     (ctr as u64, end_ctr as u64)
 }
 
+/// Always writes the current layout, so legacy data is upgraded in place when the
+/// account has room for the version byte and category. Otherwise it needs resizing first.
 pub fn write_data(
     item_class: &mut Account<ItemClass>,
     item_class_data: &ItemClassData,
@@ -417,12 +673,59 @@ pub fn write_data(
     let mut cursor = std::io::Cursor::new(dst);
     msg!("Cursor is at {}", ctr);
     cursor.set_position(ctr);
-    AnchorSerialize::serialize(&item_class_data.settings, &mut cursor)?;
-    AnchorSerialize::serialize(&item_class_data.config, &mut cursor)?;
+    AnchorSerialize::serialize(&LAYOUT_VERSION, &mut cursor)?;
+    AnchorSerialize::serialize(item_class_data, &mut cursor)?;
 
     Ok(())
 }
 
+pub fn apply_item_class_data_patch(item_class_data: &mut ItemClassData, patch: ItemClassDataPatch) {
+    let ItemClassDataPatch {
+        category,
+        settings,
+        usage_root,
+        usage_state_root,
+        component_root,
+        usages,
+        components,
+    } = patch;
+    let config = &mut item_class_data.config;
+
+    if let Some(mut category) = category {
+        category.inherited = InheritanceState::NotInherited;
+        item_class_data.category = Some(category);
+    }
+    if let Some(settings) = settings {
+        item_class_data.settings = settings;
+    }
+    if usage_root.is_some() {
+        config.usage_root = usage_root;
+    }
+    if usage_state_root.is_some() {
+        config.usage_state_root = usage_state_root;
+    }
+    if component_root.is_some() {
+        config.component_root = component_root;
+    }
+    if let Some(patched_usages) = usages {
+        let current = config.usages.get_or_insert_with(Vec::new);
+        for usage in patched_usages {
+            match current.iter_mut().find(|u| u.index == usage.index) {
+                Some(existing) => *existing = usage,
+                None => current.push(usage),
+            }
+        }
+    }
+    if components.is_some() {
+        config.components = components;
+    }
+}
+
+/// Bytes write_data needs after the ItemClass struct.
+pub fn item_class_data_len(item_class_data: &ItemClassData) -> Result<usize> {
+    Ok(1 + AnchorSerialize::try_to_vec(item_class_data)?.len())
+}
+
 #[inline(always)]
 pub fn spl_token_transfer(params: TokenTransferParams<'_, '_>) -> Result<()> {
     let TokenTransferParams {
@@ -550,6 +853,164 @@ pub fn create_or_allocate_account_raw<'a>(
     Ok(())
 }
 
+pub struct ReallocWithRentParams<'a, 'b> {
+    pub account: &'b AccountInfo<'a>,
+    pub payer: &'b AccountInfo<'a>,
+    pub system_program: &'b AccountInfo<'a>,
+    pub rent: &'b AccountInfo<'a>,
+    pub new_len: usize,
+}
+
+/// Grows an account we own to new_len, with the payer topping up rent.
+pub fn realloc_with_rent(params: ReallocWithRentParams<'_, '_>) -> Result<()> {
+    let ReallocWithRentParams {
+        account,
+        payer,
+        system_program,
+        rent,
+        new_len,
+    } = params;
+
+    if new_len.saturating_sub(account.data_len()) > MAX_PERMITTED_DATA_INCREASE {
+        return Err(error!(ErrorCode::AccountGrowthTooLarge));
+    }
+
+    let rent = &Rent::from_account_info(rent)?;
+    let required_lamports = rent
+        .minimum_balance(new_len)
+        .saturating_sub(account.lamports());
+
+    if required_lamports > 0 {
+        msg!("Transfer {} lamports to the account", required_lamports);
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, required_lamports),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    account.realloc(new_len, false)?;
+    Ok(())
+}
+
+pub struct ResizeItemClassArgs<'a, 'info> {
+    pub item_class: &'a Account<'info, ItemClass>,
+    pub item_class_data: &'a ItemClassData,
+    pub payer: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub rent: &'a AccountInfo<'info>,
+    pub allow_shrink: bool,
+}
+
+/// Sizes the item class account to exactly fit the data about to be written by write_data.
+/// Growing is paid for by the payer. Shrinking, when allowed, hands the rent no longer
+/// needed back to the payer, so only allow it once the caller has passed a permission check.
+pub fn resize_item_class(args: ResizeItemClassArgs) -> Result<()> {
+    let ResizeItemClassArgs {
+        item_class,
+        item_class_data,
+        payer,
+        system_program,
+        rent,
+        allow_shrink,
+    } = args;
+
+    let item_class_info = item_class.to_account_info();
+    let new_len = get_class_data_offset(item_class) + item_class_data_len(item_class_data)?;
+    let old_len = item_class_info.data_len();
+
+    if new_len > old_len {
+        realloc_with_rent(ReallocWithRentParams {
+            account: &item_class_info,
+            payer,
+            system_program,
+            rent,
+            new_len,
+        })?;
+    } else if new_len < old_len && allow_shrink {
+        item_class_info.realloc(new_len, false)?;
+
+        let rent = &Rent::from_account_info(rent)?;
+        let refund = item_class_info
+            .lamports()
+            .saturating_sub(rent.minimum_balance(new_len));
+
+        if refund > 0 {
+            msg!("Refund {} lamports to the payer", refund);
+            **item_class_info.lamports.borrow_mut() = item_class_info
+                .lamports()
+                .checked_sub(refund)
+                .ok_or(ErrorCode::NumericalOverflowError)?;
+            **payer.lamports.borrow_mut() = payer
+                .lamports()
+                .checked_add(refund)
+                .ok_or(ErrorCode::NumericalOverflowError)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub struct AddItemClassChildArgs<'a, 'info> {
+    pub parent: &'a AccountInfo<'info>,
+    pub parent_children: &'a AccountInfo<'info>,
+    pub bump: u8,
+    pub child: Pubkey,
+    pub payer: &'a Signer<'info>,
+    pub system_program: &'a Program<'info, System>,
+    pub rent: &'a Sysvar<'info, Rent>,
+}
+
+/// Adds the child to the parent's ItemClassChildren, creating it on the first child
+/// and growing it by a key after that.
+pub fn add_item_class_child(args: AddItemClassChildArgs) -> Result<()> {
+    let AddItemClassChildArgs {
+        parent,
+        parent_children,
+        bump,
+        child,
+        payer,
+        system_program,
+        rent,
+    } = args;
+
+    let mut children: Account<'_, ItemClassChildren> = if parent_children.data_is_empty() {
+        create_or_allocate_account_raw(
+            crate::id(),
+            parent_children,
+            &rent.to_account_info(),
+            &system_program.to_account_info(),
+            &payer.to_account_info(),
+            MIN_ITEM_CLASS_CHILDREN_SIZE,
+            &[
+                PREFIX.as_bytes(),
+                parent.key.as_ref(),
+                CHILDREN.as_bytes(),
+                &[bump],
+            ],
+        )?;
+        let mut children: Account<'_, ItemClassChildren> =
+            Account::try_from_unchecked(parent_children)?;
+        children.parent = *parent.key;
+        children.bump = bump;
+        children
+    } else {
+        Account::try_from(parent_children)?
+    };
+
+    if !children.children.contains(&child) {
+        realloc_with_rent(ReallocWithRentParams {
+            account: parent_children,
+            payer: &payer.to_account_info(),
+            system_program: &system_program.to_account_info(),
+            rent: &rent.to_account_info(),
+            new_len: MIN_ITEM_CLASS_CHILDREN_SIZE + 32 * (children.children.len() + 1),
+        })?;
+        children.children.push(child);
+    }
+    children.exit(&crate::id())?;
+    Ok(())
+}
+
 pub fn spl_token_mint_to<'a: 'b, 'b>(
     mint: AccountInfo<'a>,
     destination: AccountInfo<'a>,
@@ -629,10 +1090,10 @@ pub struct AssertPermissivenessAccessArgs<'a, 'b, 'c, 'info> {
 }
 
 pub fn assert_builder_must_be_holder_check(
-    item_class_data: &ItemClassData,
+    builder_must_be_holder: &Option<Boolean>,
     new_item_token_holder: &UncheckedAccount,
 ) -> Result<()> {
-    if let Some(b) = &item_class_data.settings.builder_must_be_holder {
+    if let Some(b) = builder_must_be_holder {
         if b.boolean {
             require!(new_item_token_holder.is_signer, MustBeHolderToBuild)
         }
@@ -641,6 +1102,21 @@ pub fn assert_builder_must_be_holder_check(
     Ok(())
 }
 
+/// How many remaining accounts assert_permissiveness_access reads for these args, so a
+/// second check can be handed the ones after them.
+pub fn permissiveness_accounts_len(
+    permissiveness_to_use: &Option<PermissivenessType>,
+    permissiveness_array: &Option<Vec<Permissiveness>>,
+    account_mint: Option<&Pubkey>,
+) -> usize {
+    let mint_len = if account_mint.is_some() { 0 } else { 1 };
+    match (permissiveness_to_use, permissiveness_array) {
+        (Some(PermissivenessType::ParentTokenHolder), Some(_)) => 4,
+        (Some(PermissivenessType::Anybody), Some(_)) => 0,
+        _ => 2 + mint_len,
+    }
+}
+
 pub fn assert_permissiveness_access(args: AssertPermissivenessAccessArgs) -> Result<()> {
     let AssertPermissivenessAccessArgs {
         program_id,
@@ -985,6 +1461,13 @@ pub fn update_item_class_with_inherited_information(
                             overridable: update_perm.overridable,
                         });
                     },
+                    ChildUpdatePropagationPermissivenessType::Category => {
+                        item_class_data.category = propagate_parent(PropagateParentArgs {
+                            parent: &parent_item_data.category,
+                            child: &item_class_data.category,
+                            overridable: update_perm.overridable,
+                        });
+                    },
                 }
             }
         }
@@ -1044,16 +1527,11 @@ pub fn assert_valid_item_settings_for_edition_type(
 }
 
 pub fn grab_parent(artifact: &AccountInfo) -> Result<Pubkey> {
+    let offset = raindrops_namespace::artifact::ArtifactNamespaces::load(artifact)?.end_offset();
     let data = artifact.data.borrow();
 
-    let number = if data[8] == 1 {
-        u32::from_le_bytes(*array_ref![data, 9, 4]) as usize
-    } else {
-        0
-    };
-    let offset = 8_usize + number * 34 + if data[8] == 1 { 4 } else { 0 } + 1;
-
-    if data[offset] == 1 {
+    // Option tag on classes. On items it is the layout version, which is never 0.
+    if data[offset] != 0 {
         let key_bytes = array_ref![data, offset + 1, 32];
         let key = Pubkey::new_from_array(*key_bytes);
         Ok(key)
@@ -1180,6 +1658,51 @@ pub fn assert_part_of_namespace<'a, 'b>(
     Ok(deserialized)
 }
 
+pub struct AssertItemClassMintArgs<'b, 'info> {
+    pub item_class_info: &'b AccountInfo<'info>,
+    pub item_mint: &'b Account<'info, Mint>,
+    pub metadata: &'b AccountInfo<'info>,
+    pub edition: &'b AccountInfo<'info>,
+    pub item_class_data: &'b ItemClassData,
+    pub remaining_accounts: &'b [AccountInfo<'info>],
+}
+
+/// Checks the mint and metadata a new item class is made for. Without an edition the class
+/// is fungible and takes over minting, so the mint authority and the token program must be
+/// the last two remaining accounts.
+pub fn assert_item_class_mint(args: AssertItemClassMintArgs) -> Result<()> {
+    let AssertItemClassMintArgs {
+        item_class_info,
+        item_mint,
+        metadata,
+        edition,
+        item_class_data,
+        remaining_accounts,
+    } = args;
+
+    let edition_option = if edition.data_len() > 0 {
+        Some(edition)
+    } else {
+        require!(remaining_accounts.len() >= 2, MintAuthorityAccountsMissing);
+        let mint_authority_info = &remaining_accounts[remaining_accounts.len() - 2];
+        let token_program_info = &remaining_accounts[remaining_accounts.len() - 1];
+        assert_keys_equal(*token_program_info.key, spl_token::id())?;
+        assert_mint_authority_matches_mint(&item_mint.mint_authority, mint_authority_info)?;
+        if item_mint.mint_authority != COption::Some(item_class_info.key()) {
+            transfer_mint_authority(TransferMintAuthorityArgs {
+                item_class_key: &item_class_info.key(),
+                item_class_info,
+                mint_authority_info,
+                token_program_info,
+                mint: item_mint,
+            })?;
+        }
+        None
+    };
+    assert_valid_item_settings_for_edition_type(edition_option, item_class_data)?;
+    assert_metadata_valid(metadata, edition_option, &item_mint.key())
+}
+
 pub struct TransferMintAuthorityArgs<'b, 'info> {
     pub item_class_key: &'b Pubkey,
     pub item_class_info: &'b AccountInfo<'info>,
@@ -1432,8 +1955,9 @@ pub fn verify_component(args: VerifyComponentArgs) -> Result<Component> {
         count_check,
     } = args;
 
-    let item_class_data = item_class.item_class_data(&item_class.to_account_info().data)?;
-    let chosen_component = if let Some(component_root) = &item_class_data.config.component_root {
+    let item_class_info = item_class.to_account_info();
+    let reader = ItemClassDataReader::new(item_class, &item_class_info.data)?;
+    let chosen_component = if let Some(component_root) = reader.component_root()? {
         if let Some(p) = component_proof {
             if let Some(c) = component {
                 // Verify the merkle proof.
@@ -1451,18 +1975,8 @@ pub fn verify_component(args: VerifyComponentArgs) -> Result<Component> {
         } else {
             return Err(error!(ErrorCode::MissingMerkleInfo));
         }
-    } else if let Some(components) = &item_class_data.config.components {
-        let mut counter: usize = 0;
-        let mut comp = components[0].clone();
-        let step = item_escrow.step as usize;
-        for c in components {
-            if c.component_scope == component_scope {
-                if counter == step {
-                    comp = c.clone();
-                }
-                counter += 1;
-            }
-        }
+    } else if let Some(first_component) = reader.first_component()? {
+        let (comp, counter) = reader.component(&component_scope, item_escrow.step as usize)?;
 
         if count_check {
             require!(
@@ -1471,7 +1985,7 @@ pub fn verify_component(args: VerifyComponentArgs) -> Result<Component> {
             );
         }
 
-        comp
+        comp.unwrap_or(first_component)
     } else {
         return Err(error!(ErrorCode::MustUseMerkleOrComponentList));
     };
@@ -1487,13 +2001,13 @@ pub fn verify_component(args: VerifyComponentArgs) -> Result<Component> {
 pub fn propagate_item_class_data_fields_to_item_data(
     item: &mut Account<Item>,
     item_class: &Account<ItemClass>,
-    item_class_data: &ItemClassData,
-) {
+    reader: &ItemClassDataReader,
+) -> Result<()> {
     item.namespaces = item_class.namespaces.clone();
 
-    item.data.usage_state_root = item_class_data.config.usage_state_root.clone();
+    item.data.usage_state_root = reader.usage_state_root()?;
 
-    if let Some(item_usage) = &item_class_data.config.usages {
+    if let Some(usage_indices) = reader.usage_indices()? {
         let mut new_states: Vec<ItemUsageState> = vec![];
 
         let mut states_length = 0;
@@ -1502,7 +2016,7 @@ pub fn propagate_item_class_data_fields_to_item_data(
         }
 
         let mut existing_values: Vec<(Option<u64>, u64)> =
-            vec![(None, 0); std::cmp::max(item_usage.len(), states_length)];
+            vec![(None, 0); std::cmp::max(usage_indices.len(), states_length)];
 
         if let Some(states) = &item.data.usage_states {
             for i in 0..states.len() {
@@ -1511,16 +2025,18 @@ pub fn propagate_item_class_data_fields_to_item_data(
             }
         }
 
-        for usage in item_usage {
+        for index in usage_indices {
             new_states.push(ItemUsageState {
-                uses: existing_values[usage.index as usize].1,
-                activated_at: existing_values[usage.index as usize].0,
-                index: usage.index,
+                uses: existing_values[index as usize].1,
+                activated_at: existing_values[index as usize].0,
+                index,
             })
         }
 
         item.data.usage_states = Some(new_states);
     }
+
+    Ok(())
 }
 
 pub fn create_program_token_account_if_not_present<'a>(
@@ -1789,8 +2305,9 @@ pub fn get_item_usage(args: GetItemUsageArgs) -> Result<ItemUsage> {
         usage,
     } = args;
 
-    let item_class_data = item_class.item_class_data(&item_class.to_account_info().data)?;
-    let item_usage = if let Some(usage_root) = &item_class_data.config.usage_root {
+    let item_class_info = item_class.to_account_info();
+    let reader = ItemClassDataReader::new(item_class, &item_class_info.data)?;
+    let item_usage = if let Some(usage_root) = reader.usage_root()? {
         if let Some(usage_proof) = &usage_proof {
             if let Some(us) = &usage {
                 // Verify the merkle proof.
@@ -1807,18 +2324,10 @@ pub fn get_item_usage(args: GetItemUsageArgs) -> Result<ItemUsage> {
         } else {
             return Err(error!(ErrorCode::MissingMerkleInfo));
         }
-    } else if let Some(usages) = &item_class_data.config.usages {
-        if usages.is_empty() {
-            return Err(error!(ErrorCode::CannotUseItemWithoutUsageOrMerkle));
-        } else {
-            let mut usage = &usages[0];
-            for usage_n in usages {
-                if usage_n.index == usage_index {
-                    usage = usage_n;
-                    break;
-                }
-            }
-            usage.clone()
+    } else if let Some(first_usage) = reader.first_item_usage()? {
+        match reader.item_usage(usage_index)? {
+            Some(usage) => usage,
+            None => first_usage,
         }
     } else {
         return Err(error!(ErrorCode::CannotUseItemWithoutUsageOrMerkle));
@@ -1826,3 +2335,284 @@ pub fn get_item_usage(args: GetItemUsageArgs) -> Result<ItemUsage> {
 
     Ok(item_usage)
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{ComponentCondition, ItemClassConfig, ItemClassSettings, NamespaceAndIndex},
+    };
+
+    fn boolean(inherited: InheritanceState, boolean: bool) -> Option<Boolean> {
+        Some(Boolean { inherited, boolean })
+    }
+
+    fn root(root: u8) -> Option<Root> {
+        Some(Root {
+            inherited: InheritanceState::NotInherited,
+            root: [root; 32],
+        })
+    }
+
+    fn permissiveness(permissiveness_type: PermissivenessType) -> Option<Vec<Permissiveness>> {
+        Some(vec![Permissiveness {
+            inherited: InheritanceState::NotInherited,
+            permissiveness_type,
+        }])
+    }
+
+    fn header() -> ItemClass {
+        ItemClass {
+            namespaces: Some(vec![NamespaceAndIndex {
+                namespace: Pubkey::new_unique(),
+                indexed: true,
+                inherited: InheritanceState::NotInherited,
+            }]),
+            parent: None,
+            mint: Some(Pubkey::new_unique()),
+            metadata: Some(Pubkey::new_unique()),
+            edition: None,
+            bump: 254,
+            existing_children: 3,
+        }
+    }
+
+    /// Every field is set and no two neighbours encode alike, so a reader that skips the
+    /// wrong way lands on the wrong value.
+    fn settings() -> ItemClassSettings {
+        ItemClassSettings {
+            free_build: boolean(InheritanceState::NotInherited, false),
+            children_must_be_editions: boolean(InheritanceState::Inherited, true),
+            builder_must_be_holder: boolean(InheritanceState::Overridden, true),
+            update_permissiveness: permissiveness(PermissivenessType::UpdateAuthority),
+            build_permissiveness: permissiveness(PermissivenessType::TokenHolder),
+            staking_warm_up_duration: Some(60),
+            staking_cooldown_duration: Some(120),
+            staking_permissiveness: permissiveness(PermissivenessType::Anybody),
+            unstaking_permissiveness: permissiveness(PermissivenessType::ParentTokenHolder),
+            child_update_propagation_permissiveness: Some(vec![
+                ChildUpdatePropagationPermissiveness {
+                    overridable: true,
+                    inherited: InheritanceState::NotInherited,
+                    child_update_propagation_permissiveness_type:
+                        ChildUpdatePropagationPermissivenessType::Usages,
+                },
+            ]),
+        }
+    }
+
+    fn usage(index: u16, item_class_type: ItemClassType) -> ItemUsage {
+        ItemUsage {
+            index,
+            basic_item_effects: None,
+            usage_permissiveness: vec![PermissivenessType::TokenHolder],
+            inherited: InheritanceState::NotInherited,
+            item_class_type,
+            callback: None,
+            validation: None,
+            do_not_pair_with_self: false,
+            dnp: None,
+        }
+    }
+
+    fn component(amount: u64, component_scope: &str) -> Component {
+        Component {
+            mint: Pubkey::new_from_array([9; 32]),
+            class_index: 0,
+            amount,
+            time_to_build: None,
+            component_scope: component_scope.to_string(),
+            use_usage_index: 0,
+            condition: ComponentCondition::Consumed,
+            inherited: InheritanceState::NotInherited,
+        }
+    }
+
+    fn config() -> ItemClassConfig {
+        ItemClassConfig {
+            usage_root: root(5),
+            usage_state_root: root(7),
+            component_root: root(8),
+            usages: Some(vec![
+                usage(
+                    4,
+                    ItemClassType::Wearable {
+                        body_part: vec!["head".to_string()],
+                        limit_per_part: Some(1),
+                    },
+                ),
+                usage(
+                    1,
+                    ItemClassType::Consumable {
+                        max_uses: Some(3),
+                        max_players_per_use: None,
+                        item_usage_type: ItemUsageType::Exhaustion,
+                        cooldown_duration: Some(10),
+                        warmup_duration: None,
+                    },
+                ),
+            ]),
+            components: Some(vec![
+                component(2, "none"),
+                component(3, "fast"),
+                component(4, "none"),
+            ]),
+        }
+    }
+
+    /// Account data of an item class as the baseline program wrote it: the header,
+    /// then the settings and config with no layout version or category.
+    fn legacy_account_data(item_class: &ItemClass) -> Vec<u8> {
+        let mut data = vec![0; 8];
+        data.extend(item_class.try_to_vec().unwrap());
+        data.extend(settings().try_to_vec().unwrap());
+        data.extend(config().try_to_vec().unwrap());
+        data
+    }
+
+    /// Account data of an item class written in the current layout.
+    fn account_data(item_class: &ItemClass) -> Vec<u8> {
+        let mut data = vec![0; 8];
+        data.extend(item_class.try_to_vec().unwrap());
+        data.push(LAYOUT_VERSION);
+        let item_class_data = ItemClassData {
+            category: Some(ItemCategory {
+                category: "hat".to_string(),
+                inherited: InheritanceState::NotInherited,
+            }),
+            settings: settings(),
+            config: config(),
+        };
+        data.extend(item_class_data.try_to_vec().unwrap());
+        data
+    }
+
+    fn encoded<T: AnchorSerialize>(value: &T) -> Vec<u8> {
+        value.try_to_vec().unwrap()
+    }
+
+    #[test]
+    fn decodes_legacy_item_class() {
+        let header = header();
+        let mut data = legacy_account_data(&header);
+        let end = data.len();
+        let data = RefCell::new(&mut data[..]);
+
+        let item_class: ItemClass =
+            AnchorDeserialize::deserialize(&mut &data.borrow()[8..]).unwrap();
+        assert_eq!(encoded(&item_class), encoded(&header));
+        assert_eq!(
+            item_class.layout_version(&data).unwrap(),
+            LEGACY_LAYOUT_VERSION
+        );
+        assert_eq!(get_class_write_offsets(&item_class, &data).1 as usize, end);
+
+        let item_class_data = item_class.item_class_data(&data).unwrap();
+        assert!(item_class_data.category.is_none());
+        assert_eq!(encoded(&item_class_data.settings), encoded(&settings()));
+        assert_eq!(encoded(&item_class_data.config), encoded(&config()));
+    }
+
+    /// Each reader field must decode to what borsh gives for the whole item class data.
+    fn assert_reader_matches_borsh(data: &mut [u8]) {
+        let data = RefCell::new(data);
+        let item_class: ItemClass =
+            AnchorDeserialize::deserialize(&mut &data.borrow()[8..]).unwrap();
+        let ItemClassData {
+            settings, config, ..
+        } = item_class.item_class_data(&data).unwrap();
+        let usages = config.usages.clone().unwrap();
+        let components = config.components.clone().unwrap();
+
+        let reader = ItemClassDataReader::new(&item_class, &data).unwrap();
+        assert_eq!(
+            encoded(&reader.free_build().unwrap()),
+            encoded(&settings.free_build)
+        );
+        assert_eq!(
+            encoded(&reader.children_must_be_editions().unwrap()),
+            encoded(&settings.children_must_be_editions)
+        );
+        assert_eq!(
+            encoded(&reader.builder_must_be_holder().unwrap()),
+            encoded(&settings.builder_must_be_holder)
+        );
+        assert_eq!(
+            encoded(&reader.update_permissiveness().unwrap()),
+            encoded(&settings.update_permissiveness)
+        );
+        assert_eq!(
+            encoded(&reader.build_permissiveness().unwrap()),
+            encoded(&settings.build_permissiveness)
+        );
+        assert_eq!(
+            encoded(&reader.usage_root().unwrap()),
+            encoded(&config.usage_root)
+        );
+        assert_eq!(
+            encoded(&reader.usage_state_root().unwrap()),
+            encoded(&config.usage_state_root)
+        );
+        assert_eq!(
+            encoded(&reader.component_root().unwrap()),
+            encoded(&config.component_root)
+        );
+
+        assert_eq!(reader.usage_indices().unwrap(), Some(vec![4, 1]));
+        assert_eq!(
+            encoded(&reader.first_item_usage().unwrap()),
+            encoded(&usages.first())
+        );
+        assert_eq!(
+            encoded(&reader.item_usage(1).unwrap()),
+            encoded(&usages.get(1))
+        );
+        assert!(reader.item_usage(2).unwrap().is_none());
+
+        assert_eq!(
+            encoded(&reader.first_component().unwrap()),
+            encoded(&components.first())
+        );
+        assert_eq!(reader.component_count("none").unwrap(), Some(2));
+        assert_eq!(reader.component_count("slow").unwrap(), Some(0));
+        let (chosen, count) = reader.component("none", 1).unwrap();
+        assert_eq!(encoded(&chosen), encoded(&components.get(2)));
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn reader_matches_borsh_layout() {
+        assert_reader_matches_borsh(&mut account_data(&header()));
+    }
+
+    #[test]
+    fn reader_matches_legacy_borsh_layout() {
+        assert_reader_matches_borsh(&mut legacy_account_data(&header()));
+    }
+
+    #[test]
+    fn reader_reports_missing_lists() {
+        let mut data = vec![0; 8];
+        data.extend(header().try_to_vec().unwrap());
+        data.push(LAYOUT_VERSION);
+        let item_class_data = ItemClassData {
+            category: None,
+            settings: settings(),
+            config: ItemClassConfig {
+                usages: None,
+                components: None,
+                ..config()
+            },
+        };
+        data.extend(item_class_data.try_to_vec().unwrap());
+        let data = RefCell::new(&mut data[..]);
+
+        let item_class: ItemClass =
+            AnchorDeserialize::deserialize(&mut &data.borrow()[8..]).unwrap();
+        let reader = ItemClassDataReader::new(&item_class, &data).unwrap();
+        assert!(reader.usage_indices().unwrap().is_none());
+        assert!(reader.first_item_usage().unwrap().is_none());
+        assert!(reader.component_count("none").unwrap().is_none());
+        assert!(reader.first_component().unwrap().is_none());
+    }
+}
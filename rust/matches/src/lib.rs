@@ -25,8 +25,10 @@ use {
     },
     spl_token::instruction::{initialize_account2, mint_to},
 };
-anchor_lang::declare_id!("mtchsiT6WoLQ62fwCoiHMCfXJzogtfru4ovY8tXKrjJ");
-pub const PREFIX: &str = "matches";
+anchor_lang::declare_id!(raindrops_common::ids::program_id(
+    raindrops_common::ids::MATCH_ID
+));
+pub const PREFIX: &str = raindrops_common::seeds::MATCHES_PREFIX;
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct CreateOrUpdateOracleArgs {
@@ -80,6 +82,11 @@ pub struct LeaveMatchArgs {
     amount: u64,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct GrowNamespaceArrayArgs {
+    additional_namespace_slots: u16,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct DisburseTokensByOracleArgs {
     token_delta_proof_info: Option<TokenDeltaProofInfo>,
@@ -266,6 +273,14 @@ pub mod matches {
 
         if win_oracle_instance.finalized {
             match_instance.state = MatchState::Finalized;
+            raindrops_namespace::utils::record_stat_in_namespaces(
+                raindrops_namespace::utils::RecordStatInNamespacesArgs {
+                    artifact: &match_instance.to_account_info(),
+                    stat: raindrops_namespace::NamespaceStat::MatchPlayed,
+                    program_id: ctx.program_id,
+                    remaining_accounts: ctx.remaining_accounts,
+                },
+            )?;
         } else {
             match_instance.state = MatchState::Started;
         }
@@ -331,6 +346,27 @@ pub mod matches {
         Ok(())
     }
 
+    /// Adds empty namespace slots to a match. Only this program can realloc it, the
+    /// namespace program's grow_namespace_array forwards here. Anybody can call this,
+    /// the payer covers the extra rent.
+    pub fn grow_namespace_array<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, GrowNamespaceArray<'info>>,
+        args: GrowNamespaceArrayArgs,
+    ) -> Result<()> {
+        let artifact = &ctx.accounts.artifact.to_account_info();
+        assert_owned_by(artifact, ctx.program_id)?;
+
+        raindrops_namespace::utils::grow_namespace_array(
+            raindrops_namespace::utils::GrowNamespaceArrayParams {
+                artifact,
+                payer: &ctx.accounts.payer.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+                rent: &ctx.accounts.rent.to_account_info(),
+                additional_slots: args.additional_namespace_slots as usize,
+            },
+        )
+    }
+
     pub fn leave_match<'a, 'b, 'c, 'info>(
         ctx: Context<'a, 'b, 'c, 'info, LeaveMatch<'info>>,
         _args: LeaveMatchArgs,
@@ -556,6 +592,11 @@ pub mod matches {
             Some(&token_transfer_authority.key()),
         )?;
 
+        raindrops_namespace::utils::assert_not_frozen_in_namespaces(
+            &source_item_or_player_pda.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
+
         if match_instance.join_allowed_during_start {
             require!(
                 match_instance.state == MatchState::Initialized
@@ -581,7 +622,9 @@ pub mod matches {
                         &validation,
                         source_item_or_player_pda,
                         token_mint,
+                        source_token_account,
                         validation_program,
+                        ctx.remaining_accounts,
                     )? {
                         return Err(error!(ErrorCode::InvalidValidation));
                     }
@@ -598,7 +641,9 @@ pub mod matches {
                     &val,
                     source_item_or_player_pda,
                     token_mint,
+                    source_token_account,
                     validation_program,
+                    ctx.remaining_accounts,
                 )? {
                     validation = true;
                     break;
@@ -657,6 +702,17 @@ pub struct UpdateMatchFromOracle<'info> {
     clock: Sysvar<'info, Clock>,
 }
 
+#[derive(Accounts)]
+pub struct GrowNamespaceArray<'info> {
+    ///CHECK: a match of this program, checked in grow_namespace_array
+    #[account(mut)]
+    artifact: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
 #[derive(Accounts)]
 pub struct DrainMatch<'info> {
     #[account(mut, constraint=match_instance.authority == authority.key(), seeds=[PREFIX.as_bytes(), match_instance.win_oracle.as_ref()], bump=match_instance.bump)]
@@ -16,7 +16,7 @@ use {
         },
         AnchorSerialize, Key, ToAccountInfo,
     },
-    anchor_spl::token::{Mint, Token},
+    anchor_spl::token::{Mint, Token, TokenAccount},
     arrayref::array_ref,
     spl_associated_token_account::get_associated_token_address,
     spl_token::instruction::close_account,
@@ -313,18 +313,7 @@ pub fn verify(proof: &Vec<[u8; 32]>, root: &[u8; 32], leaf: [u8; 32]) -> bool {
 }
 
 pub fn is_part_of_namespace<'a>(artifact: &AccountInfo<'a>, namespace: &Pubkey) -> bool {
-    let data = artifact.data.borrow_mut();
-    let number = u32::from_le_bytes(*array_ref![data, 8, 4]) as usize;
-    let offset = 12 as usize;
-    for i in 0..number {
-        let key_bytes = array_ref![data, offset + i * 33, 32];
-        let key = Pubkey::new_from_array(*key_bytes);
-        if key == *namespace {
-            return true;
-        }
-    }
-
-    return false;
+    raindrops_namespace::utils::is_part_of_namespace(artifact, namespace)
 }
 
 pub fn sighash(namespace: &str, name: &str) -> [u8; 8] {
@@ -336,16 +325,11 @@ pub fn sighash(namespace: &str, name: &str) -> [u8; 8] {
 }
 
 pub fn grab_parent<'a>(artifact: &AccountInfo<'a>) -> Result<Pubkey> {
+    let offset = raindrops_namespace::artifact::ArtifactNamespaces::load(artifact)?.end_offset();
     let data = artifact.data.borrow();
 
-    let number = if data[8] == 1 {
-        u32::from_le_bytes(*array_ref![data, 9, 4]) as usize
-    } else {
-        0
-    };
-    let offset = 8 as usize + number * 34 + if data[8] == 1 { 4 } else { 0 } + 1;
-
-    if data[offset] == 1 {
+    // Option tag on classes. On items it is the layout version, which is never 0.
+    if data[offset] != 0 {
         let key_bytes = array_ref![data, offset + 1, 32];
         let key = Pubkey::new_from_array(*key_bytes);
         return Ok(key);
@@ -358,7 +342,9 @@ pub fn is_valid_validation<'info>(
     val: &TokenValidation,
     source_item_or_player_pda: &UncheckedAccount<'info>,
     token_mint: &Account<'info, Mint>,
+    source_token_account: &Account<'info, TokenAccount>,
     validation_program: &UncheckedAccount<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
 ) -> Result<bool> {
     match val.filter {
         Filter::None => {
@@ -394,19 +380,27 @@ pub fn is_valid_validation<'info>(
     }
 
     if let Some(validation) = &val.validation {
-        let accounts = vec![
+        let mut accounts = vec![
             source_item_or_player_pda.to_account_info(),
             token_mint.to_account_info(),
+            source_token_account.to_account_info(),
             validation_program.to_account_info(),
         ];
 
         assert_keys_equal(validation_program.key(), validation.key)?;
 
-        let keys = vec![
+        let mut keys = vec![
             AccountMeta::new_readonly(source_item_or_player_pda.key(), false),
             AccountMeta::new_readonly(token_mint.key(), false),
+            AccountMeta::new_readonly(source_token_account.key(), false),
         ];
 
+        // Validators may need more accounts than the token itself, so hand them everything extra.
+        for acct in remaining_accounts {
+            keys.push(AccountMeta::new_readonly(acct.key(), false));
+            accounts.push(acct.clone());
+        }
+
         invoke(
             &Instruction {
                 program_id: validation.key,
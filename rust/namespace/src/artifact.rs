@@ -0,0 +1,398 @@
+//! Typed access to the `namespaces: Option<Vec<NamespaceAndIndex>>` every raindrops artifact
+//! is laid out with right after its discriminator:
+//!
+//! [discriminator: 8][option: u8][len: u32][NamespaceAndIndex; len]...
+//!
+//! Artifacts belong to other programs, so we cannot deserialize them whole. Everything that
+//! reads or writes the namespaces array goes through here instead of poking at offsets.
+use {
+    crate::{
+        utils::get_account_discriminator, ErrorCode, NamespaceAndIndex, NAMESPACE_AND_INDEX_SIZE,
+    },
+    anchor_lang::{
+        error,
+        prelude::{AccountInfo, AnchorSerialize, Pubkey, Result},
+        solana_program::borsh::try_from_slice_unchecked,
+    },
+    arrayref::array_ref,
+    std::str::FromStr,
+};
+
+const OPTION_OFFSET: usize = 8;
+const LEN_OFFSET: usize = 9;
+const ENTRIES_OFFSET: usize = 13;
+
+/// Whether the account is one of the layouts known to start with a namespaces array:
+/// Namespace, ItemClass, Item, ItemEscrow, Match, Player and PlayerClass, each owned
+/// by its own program.
+pub fn is_raindrops_artifact(artifact: &AccountInfo) -> bool {
+    let data = match artifact.try_borrow_data() {
+        Ok(data) => data,
+        Err(_) => return false,
+    };
+    if data.len() < 8 {
+        return false;
+    }
+    let discriminator = *array_ref![data, 0, 8];
+
+    let names: &[&str] = if artifact.owner == &crate::id() {
+        &["Namespace"]
+    } else if artifact.owner == &Pubkey::from_str(crate::ITEM_ID).unwrap() {
+        &["ItemClass", "Item", "ItemEscrow"]
+    } else if artifact.owner == &Pubkey::from_str(crate::MATCH_ID).unwrap() {
+        &["Match"]
+    } else if artifact.owner == &Pubkey::from_str(crate::PLAYER_ID).unwrap() {
+        &["PlayerClass", "Player"]
+    } else {
+        &[]
+    };
+
+    names
+        .iter()
+        .any(|name| get_account_discriminator(name) == discriminator)
+}
+
+/// A checked view over an artifact's namespaces array. Loading it verifies the owner and
+/// discriminator and that the whole array fits in the account, so every read and write
+/// after that stays inside the array.
+pub struct ArtifactNamespaces<'a, 'info> {
+    artifact: &'a AccountInfo<'info>,
+    allocated: bool,
+    len: usize,
+}
+
+impl<'a, 'info> ArtifactNamespaces<'a, 'info> {
+    pub fn load(artifact: &'a AccountInfo<'info>) -> Result<Self> {
+        if !is_raindrops_artifact(artifact) {
+            return Err(error!(ErrorCode::NotARaindropsArtifact));
+        }
+
+        let data = artifact.try_borrow_data()?;
+        if data.len() < LEN_OFFSET {
+            return Err(error!(ErrorCode::MalformedNamespaceArray));
+        }
+
+        match data[OPTION_OFFSET] {
+            0 => Ok(ArtifactNamespaces {
+                artifact,
+                allocated: false,
+                len: 0,
+            }),
+            1 => {
+                if data.len() < ENTRIES_OFFSET {
+                    return Err(error!(ErrorCode::MalformedNamespaceArray));
+                }
+                let len = u32::from_le_bytes(*array_ref![data, LEN_OFFSET, 4]) as usize;
+                let end = len
+                    .checked_mul(NAMESPACE_AND_INDEX_SIZE)
+                    .and_then(|size| size.checked_add(ENTRIES_OFFSET))
+                    .ok_or(ErrorCode::MalformedNamespaceArray)?;
+                if end > data.len() {
+                    return Err(error!(ErrorCode::MalformedNamespaceArray));
+                }
+                Ok(ArtifactNamespaces {
+                    artifact,
+                    allocated: true,
+                    len,
+                })
+            }
+            _ => Err(error!(ErrorCode::MalformedNamespaceArray)),
+        }
+    }
+
+    /// False when the artifact was created without any room for namespaces.
+    pub fn is_allocated(&self) -> bool {
+        self.allocated
+    }
+
+    /// Number of slots, empty ones included.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Offset of the first field after the namespaces array.
+    pub fn end_offset(&self) -> usize {
+        if self.allocated {
+            ENTRIES_OFFSET + self.len * NAMESPACE_AND_INDEX_SIZE
+        } else {
+            LEN_OFFSET
+        }
+    }
+
+    fn entry_offset(&self, slot: usize) -> Result<usize> {
+        if slot >= self.len {
+            return Err(error!(ErrorCode::NamespaceSlotOutOfRange));
+        }
+        Ok(ENTRIES_OFFSET + slot * NAMESPACE_AND_INDEX_SIZE)
+    }
+
+    pub fn get(&self, slot: usize) -> Result<NamespaceAndIndex> {
+        let offset = self.entry_offset(slot)?;
+        let data = self.artifact.try_borrow_data()?;
+        let entry: NamespaceAndIndex =
+            try_from_slice_unchecked(&data[offset..offset + NAMESPACE_AND_INDEX_SIZE])?;
+        Ok(entry)
+    }
+
+    pub fn set(&self, slot: usize, entry: &NamespaceAndIndex) -> Result<()> {
+        let offset = self.entry_offset(slot)?;
+        let bytes = entry.try_to_vec()?;
+        if bytes.len() != NAMESPACE_AND_INDEX_SIZE {
+            return Err(error!(ErrorCode::MalformedNamespaceArray));
+        }
+        let mut data = self.artifact.try_borrow_mut_data()?;
+        data[offset..offset + NAMESPACE_AND_INDEX_SIZE].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// The whole array, or None if the artifact has no room for namespaces.
+    pub fn to_vec(&self) -> Result<Option<Vec<NamespaceAndIndex>>> {
+        if !self.allocated {
+            return Ok(None);
+        }
+        let mut entries = Vec::with_capacity(self.len);
+        for slot in 0..self.len {
+            entries.push(self.get(slot)?);
+        }
+        Ok(Some(entries))
+    }
+
+    fn namespace_at(&self, slot: usize) -> Result<Pubkey> {
+        let offset = self.entry_offset(slot)?;
+        let data = self.artifact.try_borrow_data()?;
+        Ok(Pubkey::new_from_array(*array_ref![data, offset, 32]))
+    }
+
+    /// Slot holding the given namespace, if the artifact is in it.
+    pub fn position(&self, namespace: &Pubkey) -> Result<Option<usize>> {
+        for slot in 0..self.len {
+            if self.namespace_at(slot)? == *namespace {
+                return Ok(Some(slot));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn contains(&self, namespace: &Pubkey) -> Result<bool> {
+        Ok(self.position(namespace)?.is_some())
+    }
+
+    /// Puts the namespace in the first empty slot, returning which slot that was, or None if
+    /// the array is full.
+    pub fn add(&self, namespace: Pubkey) -> Result<Option<usize>> {
+        let empty = anchor_lang::solana_program::system_program::id();
+        match self.position(&empty)? {
+            Some(slot) => {
+                self.set(slot, &NamespaceAndIndex::new(namespace))?;
+                Ok(Some(slot))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Takes the namespace out, moving the entries after it up so the empty slots stay at
+    /// the end. Returns the entry that was removed.
+    pub fn remove(&self, namespace: &Pubkey) -> Result<NamespaceAndIndex> {
+        let slot = self
+            .position(namespace)?
+            .ok_or(ErrorCode::ArtifactNotPartOfNamespace)?;
+        let removed = self.get(slot)?;
+
+        let start = self.entry_offset(slot)?;
+        let end = self.end_offset();
+        {
+            let mut data = self.artifact.try_borrow_mut_data()?;
+            data.copy_within(start + NAMESPACE_AND_INDEX_SIZE..end, start);
+        }
+        self.set(self.len - 1, &NamespaceAndIndex::empty())?;
+
+        Ok(removed)
+    }
+
+    /// Sets the indexed flag for the namespace, returning what it was before.
+    pub fn set_indexed(&self, namespace: &Pubkey, indexed: bool) -> Result<bool> {
+        let slot = self
+            .position(namespace)?
+            .ok_or(ErrorCode::ArtifactNotPartOfNamespace)?;
+        let mut entry = self.get(slot)?;
+        let old_val = entry.indexed;
+        entry.indexed = indexed;
+        self.set(slot, &entry)?;
+        Ok(old_val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        anchor_lang::{error::Error, solana_program::program_error::ProgramError},
+    };
+
+    fn entry(namespace: Pubkey, indexed: bool) -> NamespaceAndIndex {
+        let mut entry = NamespaceAndIndex::new(namespace);
+        entry.indexed = indexed;
+        entry
+    }
+
+    /// A Namespace account whose array holds `entries`, followed by `trailer`.
+    fn namespace_data(entries: &[NamespaceAndIndex], trailer: &[u8]) -> Vec<u8> {
+        let mut data = get_account_discriminator("Namespace").to_vec();
+        data.push(1);
+        data.extend((entries.len() as u32).to_le_bytes());
+        for entry in entries {
+            data.extend(entry.try_to_vec().unwrap());
+        }
+        data.extend(trailer);
+        data
+    }
+
+    fn with_artifact<F: FnOnce(&AccountInfo)>(mut data: Vec<u8>, f: F) -> Vec<u8> {
+        let key = Pubkey::new_unique();
+        let owner = crate::id();
+        let mut lamports = 0;
+        let artifact = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        f(&artifact);
+        data
+    }
+
+    fn assert_error<T>(result: Result<T>, expected: ErrorCode) {
+        match result {
+            Ok(_) => panic!("expected {:?}", ProgramError::from(Error::from(expected))),
+            Err(err) => assert_eq!(
+                ProgramError::from(err),
+                ProgramError::from(Error::from(expected))
+            ),
+        }
+    }
+
+    #[test]
+    fn load_rejects_truncated_array() {
+        let mut data = namespace_data(&[entry(Pubkey::new_unique(), false)], &[]);
+        // Claim two entries with room for only one.
+        data[LEN_OFFSET..ENTRIES_OFFSET].copy_from_slice(&2u32.to_le_bytes());
+        with_artifact(data, |artifact| {
+            assert_error(
+                ArtifactNamespaces::load(artifact),
+                ErrorCode::MalformedNamespaceArray,
+            );
+        });
+
+        let data = namespace_data(&[], &[])[..LEN_OFFSET + 2].to_vec();
+        with_artifact(data, |artifact| {
+            assert_error(
+                ArtifactNamespaces::load(artifact),
+                ErrorCode::MalformedNamespaceArray,
+            );
+        });
+    }
+
+    #[test]
+    fn load_rejects_bad_option_tag() {
+        let mut data = namespace_data(&[], &[]);
+        data[OPTION_OFFSET] = 2;
+        with_artifact(data, |artifact| {
+            assert_error(
+                ArtifactNamespaces::load(artifact),
+                ErrorCode::MalformedNamespaceArray,
+            );
+        });
+    }
+
+    #[test]
+    fn load_rejects_length_overflow() {
+        let mut data = namespace_data(&[], &[0; 64]);
+        data[LEN_OFFSET..ENTRIES_OFFSET].copy_from_slice(&u32::MAX.to_le_bytes());
+        with_artifact(data, |artifact| {
+            assert_error(
+                ArtifactNamespaces::load(artifact),
+                ErrorCode::MalformedNamespaceArray,
+            );
+        });
+    }
+
+    #[test]
+    fn load_reads_unallocated_array() {
+        let mut data = get_account_discriminator("Namespace").to_vec();
+        data.extend([0, 7, 7]);
+        with_artifact(data, |artifact| {
+            let namespaces = ArtifactNamespaces::load(artifact).unwrap();
+            assert!(!namespaces.is_allocated());
+            assert_eq!(namespaces.end_offset(), LEN_OFFSET);
+            assert!(namespaces.to_vec().unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn add_fills_first_empty_slot_and_reports_full() {
+        let first = Pubkey::new_unique();
+        let data = namespace_data(&[entry(first, false), NamespaceAndIndex::empty()], &[]);
+        with_artifact(data, |artifact| {
+            let namespaces = ArtifactNamespaces::load(artifact).unwrap();
+            let second = Pubkey::new_unique();
+            assert_eq!(namespaces.add(second).unwrap(), Some(1));
+            assert_eq!(namespaces.position(&second).unwrap(), Some(1));
+            assert_eq!(namespaces.add(Pubkey::new_unique()).unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn remove_shifts_trailing_entries_up() {
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let trailer = [9u8; 5];
+        let data = namespace_data(
+            &[entry(a, false), entry(b, true), entry(c, false)],
+            &trailer,
+        );
+        let data = with_artifact(data, |artifact| {
+            let namespaces = ArtifactNamespaces::load(artifact).unwrap();
+            assert_eq!(namespaces.remove(&a).unwrap().namespace, a);
+
+            let entries = namespaces.to_vec().unwrap().unwrap();
+            assert_eq!(entries[0].namespace, b);
+            assert!(entries[0].indexed);
+            assert_eq!(entries[1].namespace, c);
+            assert_eq!(
+                entries[2].namespace,
+                anchor_lang::solana_program::system_program::id()
+            );
+            assert_error(namespaces.remove(&a), ErrorCode::ArtifactNotPartOfNamespace);
+        });
+        // Nothing after the array moves.
+        assert_eq!(data[data.len() - trailer.len()..], trailer);
+    }
+
+    #[test]
+    fn set_indexed_returns_previous_value() {
+        let namespace = Pubkey::new_unique();
+        let data = namespace_data(&[entry(namespace, false)], &[]);
+        with_artifact(data, |artifact| {
+            let namespaces = ArtifactNamespaces::load(artifact).unwrap();
+            assert!(!namespaces.set_indexed(&namespace, true).unwrap());
+            assert!(namespaces.get(0).unwrap().indexed);
+            assert!(namespaces.set_indexed(&namespace, false).unwrap());
+            assert!(!namespaces.get(0).unwrap().indexed);
+            assert_error(
+                namespaces.set_indexed(&Pubkey::new_unique(), true),
+                ErrorCode::ArtifactNotPartOfNamespace,
+            );
+        });
+    }
+}
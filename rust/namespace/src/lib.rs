@@ -1,22 +1,52 @@
+pub mod artifact;
 pub mod utils;
 
 use {
-    crate::utils::{
-        assert_can_add_to_namespace, assert_initialized, assert_metadata_valid,
-        assert_part_of_namespace, create_or_allocate_account_raw,
-        inverse_indexed_bool_for_namespace, pull_namespaces,
+    crate::{
+        artifact::ArtifactNamespaces,
+        utils::{
+            artifact_matches_gatekeeper, artifact_type_permissiveness, assert_can_add_to_namespace,
+            assert_derivation, assert_initialized, assert_metadata_valid,
+            assert_namespace_authority, assert_owned_by, assert_part_of_namespace,
+            assert_valid_multisig, charge_namespace_fee, close_index_page,
+            create_or_allocate_account_raw, get_artifact_type, inverse_indexed_bool_for_namespace,
+            is_artifact_class, is_part_of_namespace, pull_namespaces, read_artifact_mint,
+            read_item_usage_uses, set_indexed_bool_for_namespace, spl_token_transfer,
+            AssertNamespaceAuthorityArgs, ChargeNamespaceFeeArgs, FilterEvidence,
+            GrowNamespaceArrayParams, ReallocWithRentParams, TokenTransferParams,
+        },
+    },
+    anchor_lang::{
+        prelude::*,
+        solana_program::{
+            hash::hash,
+            instruction::Instruction,
+            program::{invoke, invoke_signed},
+            program_pack::Pack,
+            system_instruction,
+        },
+        AccountsClose, AnchorDeserialize, AnchorSerialize,
     },
-    anchor_lang::{prelude::*, AnchorDeserialize, AnchorSerialize},
     anchor_spl::token::{Mint, Token, TokenAccount},
     std::str::FromStr,
 };
-anchor_lang::declare_id!("nameAxQRRBnd4kLfsVoZBBXfrByZdZTkh8mULLxLyqV");
-pub const PLAYER_ID: &str = "p1exdMJcjVao65QdewkaZRUnU6VPSXhus9n2GzWfh98";
-pub const MATCH_ID: &str = "p1exdMJcjVao65QdewkaZRUnU6VPSXhus9n2GzWfh98";
-pub const ITEM_ID: &str = "p1exdMJcjVao65QdewkaZRUnU6VPSXhus9n2GzWfh98";
+anchor_lang::declare_id!(raindrops_common::ids::program_id(
+    raindrops_common::ids::NAMESPACE_ID
+));
+pub use raindrops_common::ids::{ITEM_ID, MATCH_ID, PLAYER_ID, STAKING_ID};
 
-pub const PREFIX: &str = "namespace";
+pub const PREFIX: &str = raindrops_common::seeds::NAMESPACE_PREFIX;
 const GATEKEEPER: &str = "gatekeeper";
+const MULTISIG: &str = "multisig";
+const TREASURY: &str = "treasury";
+const QUOTA: &str = "quota";
+const VALIDATOR: &str = "validator";
+const MEMBERSHIP: &str = "membership";
+const ACTIVITY: &str = "activity";
+const MIGRATION: &str = "migration";
+const STATS: &str = "stats";
+/// Seed of the PDA the item, matches and staking programs sign with when they record stats.
+pub const STATS_AUTHORITY: &str = "stats_authority";
 const MAX_WHITELIST: usize = 5;
 const MAX_CACHED_ITEMS: usize = 100;
 
@@ -34,15 +64,106 @@ pub struct UpdateNamespaceArgs {
     pretty_name: Option<String>,
     permissiveness_settings: Option<PermissivenessSettings>,
     whitelisted_staking_mints: Option<Vec<Pubkey>>,
+    fee_config: Option<NamespaceFeeConfig>,
+    limits: Option<NamespaceLimits>,
+    // Seconds a membership lasts before it can be expired, 0 makes memberships permanent
+    membership_duration: Option<u64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct JoinNamespaceArgs {
+    // Only needed to match a MerkleMint gatekeeper filter
+    mint_proof: Option<Vec<[u8; 32]>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MigrateArtifactArgs {
+    artifact_type: ArtifactType,
+    // Index page the artifact is cached on in the source, ignored if it is not cached
+    source_page: u64,
+    // Index page to cache it on in the destination, ignored if it is not cached
+    destination_page: u64,
+    // Only needed to match a MerkleMint gatekeeper filter
+    mint_proof: Option<Vec<[u8; 32]>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct CacheArtifactArgs {
     page: u64,
+    artifact_type: ArtifactType,
 }
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct UncacheArtifactArgs {
     page: u64,
+    artifact_type: ArtifactType,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct WithdrawNamespaceFeesArgs {
+    // None to withdraw SOL, otherwise the mint whose fees to withdraw
+    fee_mint: Option<Pubkey>,
+    amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ExpireNamespaceMembershipArgs {
+    // Index page the artifact is cached on, ignored if it is not cached
+    page: u64,
+    artifact_type: ArtifactType,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CompactNamespaceIndexArgs {
+    page: u64,
+    artifact_type: ArtifactType,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MigrateLegacyIndexEntryArgs {
+    // Page of the artifact's type to move it onto.
+    page: u64,
+    artifact_type: ArtifactType,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct GrowNamespaceArrayArgs {
+    additional_namespace_slots: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NamespaceMultisigArgs {
+    threshold: u8,
+    signers: Vec<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetNamespaceAuthorityArgs {
+    authority: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetNamespaceDelegatesArgs {
+    delegates: Vec<NamespaceDelegate>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NamespaceValidatorArgs {
+    code: u64,
+    require_membership: bool,
+    max_activations: Option<u64>,
+    require_pass: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetNamespaceFrozenArgs {
+    frozen: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetNamespaceParentArgs {
+    inherit_permissiveness_settings: bool,
+    inherit_whitelisted_staking_mints: bool,
+    inherit_gatekeeper: bool,
 }
 
 #[program]
@@ -113,9 +234,18 @@ pub mod namespace {
         namespace.metadata = metadata.key();
         namespace.master_edition = master_edition.key();
         namespace.highest_page = 0;
+        namespace.highest_page_by_type = [0; ARTIFACT_TYPE_COUNT];
         namespace.artifacts_cached = 0;
         namespace.artifacts_added = 0;
 
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.payer.key()),
+            action: NamespaceAction::Initialize,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
         msg!("ok");
         Ok(())
     }
@@ -128,8 +258,24 @@ pub mod namespace {
             pretty_name,
             permissiveness_settings,
             whitelisted_staking_mints,
+            fee_config,
+            limits,
+            membership_duration,
         } = args;
 
+        // Mints come first in remaining accounts, anything after is for multisig approval.
+        let mint_count = whitelisted_staking_mints
+            .as_ref()
+            .map(|ws_mints| ws_mints.len())
+            .unwrap_or(0);
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts.get(mint_count..).unwrap_or(&[]),
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
         let namespace = &mut ctx.accounts.namespace;
 
         if let Some(ws_mints) = whitelisted_staking_mints {
@@ -142,6 +288,10 @@ pub mod namespace {
                 let _mint: spl_token::state::Mint = assert_initialized(&mint_account)?;
             }
             namespace.whitelisted_staking_mints = ws_mints;
+            if let Some(inheritance) = &mut namespace.inheritance {
+                inheritance.whitelisted_staking_mints =
+                    inheritance.whitelisted_staking_mints.override_parent();
+            }
         }
         if let Some(pn) = pretty_name {
             if pn.len() > 32 {
@@ -152,8 +302,70 @@ pub mod namespace {
 
         if let Some(permissiveness) = permissiveness_settings {
             namespace.permissiveness_settings = permissiveness;
+            if let Some(inheritance) = &mut namespace.inheritance {
+                inheritance.permissiveness_settings =
+                    inheritance.permissiveness_settings.override_parent();
+            }
         }
 
+        if let Some(fc) = fee_config {
+            namespace.fee_config = Some(fc);
+        }
+
+        if let Some(lim) = limits {
+            if lim.max_per_artifact_type.len() > MAX_ARTIFACT_TYPE_LIMITS {
+                return Err(error!(ErrorCode::TooManyArtifactTypeLimits));
+            }
+            namespace.limits = Some(lim);
+        }
+
+        if let Some(duration) = membership_duration {
+            namespace.membership_duration = if duration == 0 { None } else { Some(duration) };
+        }
+
+        fit_namespace_to_data(FitNamespaceToDataArgs {
+            namespace,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::Update,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
+    }
+
+    pub fn set_namespace_frozen<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpdateNamespace<'info>>,
+        args: SetNamespaceFrozenArgs,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
+        ctx.accounts.namespace.frozen = args.frozen;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: if args.frozen {
+                NamespaceAction::Freeze
+            } else {
+                NamespaceAction::Unfreeze
+            },
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
         Ok(())
     }
 
@@ -161,14 +373,27 @@ pub mod namespace {
         ctx: Context<'_, '_, '_, 'info, CacheArtifact<'info>>,
         args: CacheArtifactArgs,
     ) -> Result<()> {
-        let CacheArtifactArgs { page } = args;
+        let CacheArtifactArgs {
+            page,
+            artifact_type,
+        } = args;
+
+        if ctx.accounts.namespace.frozen {
+            return Err(error!(ErrorCode::NamespaceFrozen));
+        }
+
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::CacheArtifacts),
+        })?;
 
         let namespace = &mut ctx.accounts.namespace;
-        let index = &mut ctx.accounts.index;
-        let prior_index = &ctx.accounts.prior_index;
         let artifact = &mut ctx.accounts.artifact;
-        let index_info = index.to_account_info();
-        let prior_index_info = prior_index.to_account_info();
+        let index_info = ctx.accounts.index.to_account_info();
+        let prior_index_info = ctx.accounts.prior_index.to_account_info();
 
         assert_part_of_namespace(artifact, namespace)?;
 
@@ -180,32 +405,24 @@ pub mod namespace {
             return Err(error!(ErrorCode::CanOnlyCacheValidRaindropsObjects));
         }
 
-        if index_info.data_is_empty() {
-            if prior_index_info.data_is_empty() {
-                return Err(error!(
-                    ErrorCode::PreviousIndexNeedsToExistBeforeCreatingThisOne
-                ));
-            } else if prior_index.caches.len() < MAX_CACHED_ITEMS {
-                return Err(error!(ErrorCode::PreviousIndexNotFull));
-            }
-            let namespace_key = namespace.key();
-            let page_str = page.to_string();
-            let signer_seeds = [
-                PREFIX.as_bytes(),
-                namespace_key.as_ref(),
-                page_str.as_bytes(),
-                &[*ctx.bumps.get("index").unwrap()],
-            ];
-            create_or_allocate_account_raw(
-                *ctx.program_id,
-                &index_info,
-                &ctx.accounts.rent.to_account_info(),
-                &ctx.accounts.system_program,
-                &ctx.accounts.payer,
-                INDEX_SIZE,
-                &signer_seeds,
-            )?;
-        } else if index.caches.len() >= MAX_CACHED_ITEMS {
+        if get_artifact_type(artifact)? != artifact_type {
+            return Err(error!(ErrorCode::ArtifactTypeMismatch));
+        }
+
+        let opened = open_index_page(OpenIndexPageArgs {
+            namespace,
+            index: &index_info,
+            prior_index: &prior_index_info,
+            artifact_type: &artifact_type,
+            page,
+            bump: *ctx.bumps.get("index").unwrap(),
+            payer: &ctx.accounts.payer,
+            rent: &ctx.accounts.rent,
+            system_program: &ctx.accounts.system_program,
+        })?;
+
+        let mut index: Account<'_, NamespaceIndex> = Account::try_from_unchecked(&index_info)?;
+        if index.caches.len() >= MAX_CACHED_ITEMS {
             return Err(error!(ErrorCode::IndexFull));
         }
 
@@ -213,17 +430,66 @@ pub mod namespace {
             .artifacts_cached
             .checked_add(1)
             .ok_or(ErrorCode::NumericalOverflowError)?;
-        if page > namespace.highest_page {
-            namespace.highest_page = page
+        if let Some(max_cached) = namespace.limits.as_ref().and_then(|lim| lim.max_cached) {
+            if namespace.artifacts_cached > max_cached {
+                return Err(error!(ErrorCode::NamespaceCacheLimitReached));
+            }
+        }
+        let type_index = artifact_type.get_index();
+        if page > namespace.highest_page_by_type[type_index] {
+            namespace.highest_page_by_type[type_index] = page
         }
+        index.bump = *ctx.bumps.get("index").unwrap();
         index.page = page;
         index.namespace = namespace.key();
-        index.caches.push(artifact.key());
+        if opened {
+            index.payer = ctx.accounts.payer.key();
+        }
+        index.caches.push(CachedArtifact {
+            key: artifact.key(),
+            is_class: is_artifact_class(artifact),
+            artifact_type: artifact_type.clone(),
+        });
+        index.artifact_type = artifact_type;
+        index.exit(ctx.program_id)?;
 
         let old_val = inverse_indexed_bool_for_namespace(artifact, namespace.key())?;
         if old_val == 1 {
             return Err(error!(ErrorCode::AlreadyCached));
         }
+
+        assert_namespace_gatekeeper(namespace, &ctx.accounts.namespace_gatekeeper)?;
+        let cache_fee = get_fee_owed(
+            namespace,
+            artifact,
+            &pull_namespaces(artifact)?,
+            &ctx.accounts.namespace_gatekeeper,
+            &FilterEvidence {
+                mint_proof: None,
+                remaining_accounts: ctx.remaining_accounts,
+            },
+            |fee_config| fee_config.cache_fee,
+        )?;
+        if cache_fee > 0 {
+            charge_namespace_fee(ChargeNamespaceFeeArgs {
+                namespace,
+                treasury: &ctx.accounts.treasury,
+                payer: &ctx.accounts.payer,
+                payer_token_account: &ctx.accounts.payer_token_account,
+                treasury_token_account: &ctx.accounts.treasury_token_account,
+                token_program: &ctx.accounts.token_program,
+                system_program: &ctx.accounts.system_program,
+                amount: cache_fee,
+            })?;
+        }
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: Some(artifact.key()),
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::Cache,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
         Ok(())
     }
 
@@ -231,7 +497,19 @@ pub mod namespace {
         ctx: Context<'_, '_, '_, 'info, UncacheArtifact<'info>>,
         args: UncacheArtifactArgs,
     ) -> Result<()> {
-        let UncacheArtifactArgs { page, .. } = args;
+        let UncacheArtifactArgs {
+            page,
+            artifact_type,
+        } = args;
+
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::CacheArtifacts),
+        })?;
+
         let namespace = &mut ctx.accounts.namespace;
         let index = &mut ctx.accounts.index;
         let artifact = &mut ctx.accounts.artifact;
@@ -242,33 +520,564 @@ pub mod namespace {
         let mut found = false;
         let mut new_arr = vec![];
         for obj in &index.caches {
-            if obj != &artifact.key() {
-                new_arr.push(obj);
+            if obj.key != artifact.key() {
+                new_arr.push(obj.clone());
             } else {
                 found = true;
             }
         }
 
-        if found {
-            inverse_indexed_bool_for_namespace(artifact, namespace.key())?;
-            namespace.artifacts_cached = namespace
-                .artifacts_cached
-                .checked_sub(1)
-                .ok_or(ErrorCode::NumericalOverflowError)?;
+        if !found {
+            return Err(error!(ErrorCode::NotCachedHere));
+        }
+
+        index.caches = new_arr;
+        inverse_indexed_bool_for_namespace(artifact, namespace.key())?;
+        namespace.artifacts_cached = namespace
+            .artifacts_cached
+            .checked_sub(1)
+            .ok_or(ErrorCode::NumericalOverflowError)?;
+
+        let type_index = artifact_type.get_index();
+        if page == namespace.highest_page_by_type[type_index] && index.caches.is_empty() {
+            if receiver.key() != index.payer {
+                return Err(error!(ErrorCode::ReceiverMustBeIndexPayer));
+            }
+            // Page 0 stays as the head of the list even when empty.
+            if page > 0 {
+                namespace.highest_page_by_type[type_index] = page
+                    .checked_sub(1)
+                    .ok_or(ErrorCode::NumericalOverflowError)?;
+            }
+            close_index_page(&index.to_account_info(), &receiver.to_account_info())?;
+        }
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: Some(artifact.key()),
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::Uncache,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
+    }
+
+    /// Anyone can call this to move artifacts off of the highest page into holes left on
+    /// a lower page by uncaching. Pass the artifacts being moved as remaining accounts, in
+    /// the order they come off the end of the highest page, then the activity log if the
+    /// namespace keeps one.
+    pub fn compact_namespace_index<'info>(
+        ctx: Context<'_, '_, '_, 'info, CompactNamespaceIndex<'info>>,
+        args: CompactNamespaceIndexArgs,
+    ) -> Result<()> {
+        let CompactNamespaceIndexArgs {
+            page,
+            artifact_type,
+        } = args;
+        let namespace = &mut ctx.accounts.namespace;
+        let index = &mut ctx.accounts.index;
+        let highest_index = &mut ctx.accounts.highest_index;
+        let receiver = &ctx.accounts.receiver;
+
+        let type_index = artifact_type.get_index();
+        if page >= namespace.highest_page_by_type[type_index] {
+            return Err(error!(ErrorCode::CanOnlyCompactIntoLowerPage));
         }
 
-        if page == namespace.highest_page && index.caches.len() == 0 {
-            namespace.highest_page = page
+        // The activity log, if the namespace keeps one, comes after the artifacts.
+        let remaining_accounts = ctx.remaining_accounts;
+        let artifacts = match namespace.activity_log.and_then(|activity_log| {
+            remaining_accounts
+                .iter()
+                .position(|acct| acct.key == &activity_log)
+        }) {
+            Some(end) => &remaining_accounts[..end],
+            None => remaining_accounts,
+        };
+
+        let mut moved = 0;
+        while index.caches.len() < MAX_CACHED_ITEMS && !highest_index.caches.is_empty() {
+            let cached = highest_index.caches.last().unwrap().clone();
+            let artifact = match artifacts.get(moved) {
+                Some(a) => a,
+                None => break,
+            };
+            if artifact.key() != cached.key {
+                return Err(error!(ErrorCode::CompactionArtifactMismatch));
+            }
+            highest_index.caches.pop();
+            moved += 1;
+
+            if artifact.data_is_empty() {
+                // Artifact was closed while still cached, just drop it.
+                namespace.artifacts_cached = namespace
+                    .artifacts_cached
+                    .checked_sub(1)
+                    .ok_or(ErrorCode::NumericalOverflowError)?;
+                continue;
+            }
+
+            set_indexed_bool_for_namespace(artifact, namespace.key(), true)?;
+            index.caches.push(cached);
+        }
+
+        if highest_index.caches.is_empty() {
+            if receiver.key() != highest_index.payer {
+                return Err(error!(ErrorCode::ReceiverMustBeIndexPayer));
+            }
+            namespace.highest_page_by_type[type_index] = namespace.highest_page_by_type[type_index]
                 .checked_sub(1)
                 .ok_or(ErrorCode::NumericalOverflowError)?;
-            let curr_lamp = index.to_account_info().lamports();
-            **index.to_account_info().lamports.borrow_mut() = 0;
+            close_index_page(
+                &highest_index.to_account_info(),
+                &receiver.to_account_info(),
+            )?;
+        }
 
-            **receiver.lamports.borrow_mut() = receiver
-                .lamports()
-                .checked_add(curr_lamp)
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: None,
+            actor: None,
+            action: NamespaceAction::Compact,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
+    }
+
+    /// Moves an artifact off of an index page from before pages were split by artifact type
+    /// onto the page for its type, keeping it cached. Old pages are drained from the highest
+    /// down, and closed once empty, so highest_page keeps pointing at the last of them.
+    pub fn migrate_legacy_index_entry<'info>(
+        ctx: Context<'_, '_, '_, 'info, MigrateLegacyIndexEntry<'info>>,
+        args: MigrateLegacyIndexEntryArgs,
+    ) -> Result<()> {
+        let MigrateLegacyIndexEntryArgs {
+            page,
+            artifact_type,
+        } = args;
+
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::CacheArtifacts),
+        })?;
+
+        let namespace = &mut ctx.accounts.namespace;
+        let artifact = &ctx.accounts.artifact;
+        let legacy_index_info = ctx.accounts.legacy_index.to_account_info();
+        let index_info = ctx.accounts.index.to_account_info();
+
+        if get_artifact_type(artifact)? != artifact_type {
+            return Err(error!(ErrorCode::ArtifactTypeMismatch));
+        }
+
+        let mut legacy_index = LegacyNamespaceIndex::load(&legacy_index_info)?;
+        let position = legacy_index
+            .caches
+            .iter()
+            .position(|key| key == &artifact.key())
+            .ok_or(ErrorCode::NotCachedHere)?;
+        legacy_index.caches.remove(position);
+
+        let opened = open_index_page(OpenIndexPageArgs {
+            namespace,
+            index: &index_info,
+            prior_index: &ctx.accounts.prior_index.to_account_info(),
+            artifact_type: &artifact_type,
+            page,
+            bump: *ctx.bumps.get("index").unwrap(),
+            payer: &ctx.accounts.payer,
+            rent: &ctx.accounts.rent,
+            system_program: &ctx.accounts.system_program,
+        })?;
+        let mut index: Account<'_, NamespaceIndex> = Account::try_from_unchecked(&index_info)?;
+        if index.caches.len() >= MAX_CACHED_ITEMS {
+            return Err(error!(ErrorCode::IndexFull));
+        }
+        let type_index = artifact_type.get_index();
+        if page > namespace.highest_page_by_type[type_index] {
+            namespace.highest_page_by_type[type_index] = page
+        }
+        index.bump = *ctx.bumps.get("index").unwrap();
+        index.page = page;
+        index.namespace = namespace.key();
+        if opened {
+            index.payer = ctx.accounts.payer.key();
+        }
+        index.caches.push(CachedArtifact {
+            key: artifact.key(),
+            is_class: is_artifact_class(artifact),
+            artifact_type: artifact_type.clone(),
+        });
+        index.artifact_type = artifact_type;
+        index.exit(ctx.program_id)?;
+
+        if legacy_index.caches.is_empty() {
+            if namespace.highest_page > 0 {
+                namespace.highest_page -= 1;
+            }
+            close_index_page(&legacy_index_info, &ctx.accounts.receiver.to_account_info())?;
+        } else {
+            legacy_index.save(&legacy_index_info)?;
+        }
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: Some(artifact.key()),
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::Cache,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
+    }
+
+    /// Anyone can call this to remove an artifact whose membership has lapsed, uncaching it
+    /// first if need be. Rent from the membership record goes back to whoever paid for it.
+    /// Memberships never lapse while the namespace has no membership duration.
+    pub fn expire_namespace_membership<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExpireNamespaceMembership<'info>>,
+        args: ExpireNamespaceMembershipArgs,
+    ) -> Result<()> {
+        let ExpireNamespaceMembershipArgs {
+            page,
+            artifact_type,
+        } = args;
+        let namespace = &mut ctx.accounts.namespace;
+        let artifact = &mut ctx.accounts.artifact;
+        let index_info = ctx.accounts.index.to_account_info();
+        let receiver = &ctx.accounts.receiver;
+
+        if namespace.membership_duration.is_none() {
+            return Err(error!(ErrorCode::MembershipDoesNotExpire));
+        }
+        if ctx.accounts.membership.expires_at > ctx.accounts.clock.unix_timestamp as u64 {
+            return Err(error!(ErrorCode::MembershipNotExpired));
+        }
+
+        let cached = pull_namespaces(artifact)?
+            .unwrap_or_default()
+            .iter()
+            .any(|n| n.namespace == namespace.key() && n.indexed);
+        if cached {
+            uncache_from_index_page(UncacheFromIndexPageArgs {
+                namespace,
+                artifact,
+                index: &index_info,
+                page,
+                artifact_type: &artifact_type,
+                receiver: &receiver.to_account_info(),
+            })?;
+        }
+
+        let artifact_type = get_artifact_type(artifact)?;
+        remove_artifact_from_namespace(artifact, namespace)?;
+        count_namespace_stat(CountNamespaceStatArgs {
+            namespace,
+            stat: NamespaceStat::Leave { artifact_type },
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: Some(artifact.key()),
+            actor: None,
+            action: NamespaceAction::ExpireMembership,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
+    }
+
+    /// Moves an artifact from one namespace to another in one go. The source authority always
+    /// signs, the destination authority signs too unless it left a migration permit for the
+    /// source, in which case the artifact has to meet the destination's permissiveness without
+    /// the authority. The artifact keeps its slot, and if it was cached it stays cached as long
+    /// as the given destination page exists and has room.
+    pub fn migrate_artifact<'info>(
+        ctx: Context<'_, '_, '_, 'info, MigrateArtifact<'info>>,
+        args: MigrateArtifactArgs,
+    ) -> Result<()> {
+        let MigrateArtifactArgs {
+            artifact_type,
+            source_page,
+            mint_proof,
+            ..
+        } = args;
+
+        if ctx.accounts.source_namespace.frozen || ctx.accounts.destination_namespace.frozen {
+            return Err(error!(ErrorCode::NamespaceFrozen));
+        }
+
+        // Each side's multisig goes in front of the co-signers in remaining accounts.
+        let with_multisig = |multisig: &UncheckedAccount<'info>| {
+            std::iter::once(multisig.to_account_info())
+                .chain(ctx.remaining_accounts.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.source_namespace,
+            namespace_token: &ctx.accounts.source_namespace_token,
+            token_holder: &ctx.accounts.source_token_holder.to_account_info(),
+            remaining_accounts: &with_multisig(&ctx.accounts.source_multisig),
+            power: Some(NamespaceAdminPower::AdmitArtifacts),
+        })?;
+        let destination_signed = assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.destination_namespace,
+            namespace_token: &ctx.accounts.destination_namespace_token,
+            token_holder: &ctx.accounts.destination_token_holder.to_account_info(),
+            remaining_accounts: &with_multisig(&ctx.accounts.destination_multisig),
+            power: Some(NamespaceAdminPower::AdmitArtifacts),
+        })
+        .is_ok();
+        if !destination_signed
+            && !has_migration_permit(&ctx.accounts.migration_permit.to_account_info())
+        {
+            return Err(error!(ErrorCode::MigrationNotApproved));
+        }
+
+        let source = &mut ctx.accounts.source_namespace;
+        let destination = &mut ctx.accounts.destination_namespace;
+        let artifact = &ctx.accounts.artifact;
+        let artifact_info = artifact.to_account_info();
+        let destination_gatekeeper = &ctx.accounts.destination_gatekeeper;
+        let receiver = ctx.accounts.receiver.to_account_info();
+
+        if get_artifact_type(&artifact_info)? != artifact_type {
+            return Err(error!(ErrorCode::ArtifactTypeMismatch));
+        }
+
+        // A permit only stands in for the destination authority's approval of the move, the
+        // artifact still has to get past the gatekeeper without the authority's signature.
+        assert_namespace_gatekeeper(destination, destination_gatekeeper)?;
+        let evidence = FilterEvidence {
+            mint_proof: mint_proof.as_deref(),
+            remaining_accounts: ctx.remaining_accounts,
+        };
+        let art_namespaces = assert_can_add_to_namespace(
+            artifact,
+            destination,
+            destination_gatekeeper,
+            destination_signed,
+            &evidence,
+        )?;
+
+        let art_names = ArtifactNamespaces::load(&artifact_info)?;
+        let slot = art_names
+            .position(&source.key())?
+            .ok_or(ErrorCode::ArtifactNotPartOfNamespace)?;
+        if art_names.contains(&destination.key())? {
+            return Err(error!(ErrorCode::ArtifactAlreadyInNamespace));
+        }
+        let was_cached = art_names.get(slot)?.indexed;
+
+        // Leave the source.
+        if was_cached {
+            uncache_from_index_page(UncacheFromIndexPageArgs {
+                namespace: source,
+                artifact: &artifact_info,
+                index: &ctx.accounts.source_index.to_account_info(),
+                page: source_page,
+                artifact_type: &artifact_type,
+                receiver: &receiver,
+            })?;
+        }
+        count_departure(source, &artifact_type)?;
+        close_membership(
+            &ctx.accounts.source_membership.to_account_info(),
+            &receiver.to_account_info(),
+        )?;
+
+        // Join the destination in the same slot.
+        art_names.set(slot, &NamespaceAndIndex::new(destination.key()))?;
+        destination.artifacts_added = destination
+            .artifacts_added
+            .checked_add(1)
+            .ok_or(ErrorCode::NumericalOverflowError)?;
+        assert_within_namespace_limits(destination, &artifact_type)?;
+        count_wallet_join(CountWalletJoinArgs {
+            namespace: destination,
+            artifact: &artifact_info,
+            artifact_token_account: &ctx.accounts.artifact_token_account.to_account_info(),
+            wallet_quota: &ctx.accounts.wallet_quota.to_account_info(),
+            payer: &ctx.accounts.payer,
+            rent: &ctx.accounts.rent,
+            system_program: &ctx.accounts.system_program,
+        })?;
+        start_membership(StartMembershipArgs {
+            namespace: destination,
+            artifact: artifact.key(),
+            membership: &ctx.accounts.destination_membership.to_account_info(),
+            bump: *ctx.bumps.get("destination_membership").unwrap(),
+            payer: &ctx.accounts.payer,
+            rent: &ctx.accounts.rent,
+            system_program: &ctx.accounts.system_program,
+            now: ctx.accounts.clock.unix_timestamp,
+        })?;
+
+        let mut fee = get_fee_owed(
+            destination,
+            artifact,
+            &art_namespaces,
+            destination_gatekeeper,
+            &evidence,
+            |fee_config| fee_config.join_fee,
+        )?;
+        if was_cached
+            && recache_on_index_page(
+                destination,
+                &artifact_info,
+                &ctx.accounts.destination_index.to_account_info(),
+                &artifact_type,
+            )?
+        {
+            let cache_fee = get_fee_owed(
+                destination,
+                artifact,
+                &art_namespaces,
+                destination_gatekeeper,
+                &evidence,
+                |fee_config| fee_config.cache_fee,
+            )?;
+            fee = fee
+                .checked_add(cache_fee)
                 .ok_or(ErrorCode::NumericalOverflowError)?;
         }
+        if fee > 0 {
+            charge_namespace_fee(ChargeNamespaceFeeArgs {
+                namespace: destination,
+                treasury: &ctx.accounts.treasury,
+                payer: &ctx.accounts.payer,
+                payer_token_account: &ctx.accounts.payer_token_account,
+                treasury_token_account: &ctx.accounts.treasury_token_account,
+                token_program: &ctx.accounts.token_program,
+                system_program: &ctx.accounts.system_program,
+                amount: fee,
+            })?;
+        }
+
+        count_namespace_stat(CountNamespaceStatArgs {
+            namespace: source,
+            stat: NamespaceStat::Leave {
+                artifact_type: artifact_type.clone(),
+            },
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        count_namespace_stat(CountNamespaceStatArgs {
+            namespace: destination,
+            stat: NamespaceStat::Join { artifact_type },
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: source,
+            artifact: Some(artifact.key()),
+            actor: Some(ctx.accounts.source_token_holder.key()),
+            action: NamespaceAction::MigrateOut,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: destination,
+            artifact: Some(artifact.key()),
+            actor: Some(ctx.accounts.payer.key()),
+            action: NamespaceAction::MigrateIn,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    /// Lets the source namespace's authority migrate artifacts in without the destination
+    /// authority signing each time.
+    pub fn create_migration_permit<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateMigrationPermit<'info>>,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::AdmitArtifacts),
+        })?;
+
+        let migration_permit = &mut ctx.accounts.migration_permit;
+        migration_permit.namespace = ctx.accounts.namespace.key();
+        migration_permit.source_namespace = ctx.accounts.source_namespace.key();
+        migration_permit.bump = *ctx.bumps.get("migration_permit").unwrap();
+        Ok(())
+    }
+
+    pub fn revoke_migration_permit<'info>(
+        ctx: Context<'_, '_, '_, 'info, RevokeMigrationPermit<'info>>,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::AdmitArtifacts),
+        })?;
+        Ok(())
+    }
+
+    pub fn renew_membership<'info>(
+        ctx: Context<'_, '_, '_, 'info, RenewMembership<'info>>,
+    ) -> Result<()> {
+        let namespace = &ctx.accounts.namespace;
+        let artifact = &ctx.accounts.artifact;
+        let membership = &mut ctx.accounts.membership;
+
+        let duration = namespace
+            .membership_duration
+            .ok_or(ErrorCode::MembershipDoesNotExpire)?;
+        if membership.expires_at == NEVER_EXPIRES {
+            return Err(error!(ErrorCode::MembershipDoesNotExpire));
+        }
+        assert_part_of_namespace(artifact, namespace)?;
+        assert_namespace_gatekeeper(namespace, &ctx.accounts.namespace_gatekeeper)?;
+
+        // Renewing a lapsed membership that has not been expired yet starts it over from now.
+        let now = ctx.accounts.clock.unix_timestamp as u64;
+        membership.expires_at = membership
+            .expires_at
+            .max(now)
+            .checked_add(duration)
+            .ok_or(ErrorCode::NumericalOverflowError)?;
+
+        let renew_fee = get_fee_owed(
+            namespace,
+            artifact,
+            &pull_namespaces(artifact)?,
+            &ctx.accounts.namespace_gatekeeper,
+            &FilterEvidence {
+                mint_proof: None,
+                remaining_accounts: ctx.remaining_accounts,
+            },
+            |fee_config| fee_config.renew_fee,
+        )?;
+        if renew_fee > 0 {
+            charge_namespace_fee(ChargeNamespaceFeeArgs {
+                namespace,
+                treasury: &ctx.accounts.treasury,
+                payer: &ctx.accounts.payer,
+                payer_token_account: &ctx.accounts.payer_token_account,
+                treasury_token_account: &ctx.accounts.treasury_token_account,
+                token_program: &ctx.accounts.token_program,
+                system_program: &ctx.accounts.system_program,
+                amount: renew_fee,
+            })?;
+        }
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: Some(artifact.key()),
+            actor: Some(ctx.accounts.payer.key()),
+            action: NamespaceAction::RenewMembership,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
 
         Ok(())
     }
@@ -277,8 +1086,24 @@ pub mod namespace {
         ctx: Context<'_, '_, '_, 'info, CreateNamespaceGatekeeper<'info>>,
         _bump: u8,
     ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::EditGatekeeper),
+        })?;
+
         let namespace_gatekeeper = &mut ctx.accounts.namespace_gatekeeper;
         namespace_gatekeeper.bump = *ctx.bumps.get("namespace_gatekeeper").unwrap();
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::CreateGatekeeper,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
         Ok(())
     }
 
@@ -286,8 +1111,30 @@ pub mod namespace {
         ctx: Context<'_, '_, '_, 'info, AddToNamespaceGatekeeper<'info>>,
         artifact_filter: ArtifactFilter,
     ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::EditGatekeeper),
+        })?;
+
         let namespace_gatekeeper = &mut ctx.accounts.namespace_gatekeeper;
         namespace_gatekeeper.artifact_filters.push(artifact_filter);
+        fit_gatekeeper_to_filters(FitGatekeeperToFiltersArgs {
+            namespace_gatekeeper,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::AddToGatekeeper,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
         Ok(())
     }
 
@@ -295,139 +1142,1590 @@ pub mod namespace {
         ctx: Context<'_, '_, '_, 'info, RemoveFromNamespaceGatekeeper<'info>>,
         idx: u64,
     ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::EditGatekeeper),
+        })?;
+
+        let namespace_gatekeeper = &mut ctx.accounts.namespace_gatekeeper;
+        let idx = assert_gatekeeper_filter_index(namespace_gatekeeper, idx)?;
+        namespace_gatekeeper.artifact_filters.remove(idx);
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::RemoveFromGatekeeper,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    pub fn replace_in_namespace_gatekeeper<'info>(
+        ctx: Context<'_, '_, '_, 'info, ReplaceInNamespaceGatekeeper<'info>>,
+        idx: u64,
+        artifact_filter: ArtifactFilter,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::EditGatekeeper),
+        })?;
+
         let namespace_gatekeeper = &mut ctx.accounts.namespace_gatekeeper;
+        let idx = assert_gatekeeper_filter_index(namespace_gatekeeper, idx)?;
+        namespace_gatekeeper.artifact_filters[idx] = artifact_filter;
+        // The new filter may be bigger than the one it replaced.
+        fit_gatekeeper_to_filters(FitGatekeeperToFiltersArgs {
+            namespace_gatekeeper,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
 
-        let mut new_arr = vec![];
-        for i in 0..namespace_gatekeeper.artifact_filters.len() {
-            if i != idx as usize {
-                new_arr.push(namespace_gatekeeper.artifact_filters[i].clone())
-            }
-        }
-        namespace_gatekeeper.artifact_filters = new_arr;
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::ReplaceInGatekeeper,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    /// Moves the filter at `from` to `to`, shifting the ones in between.
+    pub fn reorder_namespace_gatekeeper<'info>(
+        ctx: Context<'_, '_, '_, 'info, ReorderNamespaceGatekeeper<'info>>,
+        from: u64,
+        to: u64,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::EditGatekeeper),
+        })?;
+
+        let namespace_gatekeeper = &mut ctx.accounts.namespace_gatekeeper;
+        let from = assert_gatekeeper_filter_index(namespace_gatekeeper, from)?;
+        let to = assert_gatekeeper_filter_index(namespace_gatekeeper, to)?;
+        let artifact_filter = namespace_gatekeeper.artifact_filters.remove(from);
+        namespace_gatekeeper
+            .artifact_filters
+            .insert(to, artifact_filter);
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::ReorderGatekeeper,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
         Ok(())
     }
 
+    /// Removes an uncached artifact from the namespace. Needs the namespace authority or a
+    /// delegate that can admit artifacts.
     pub fn leave_namespace<'info>(
-        ctx: Context<'_, '_, '_, 'info, JoinNamespace<'info>>,
+        ctx: Context<'_, '_, '_, 'info, LeaveNamespace<'info>>,
         _namespace_gatekeeper_bump: u8,
     ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::AdmitArtifacts),
+        })?;
+
         let artifact = &mut ctx.accounts.artifact;
         let namespace = &mut ctx.accounts.namespace;
 
-        let art_namespaces = pull_namespaces(artifact)?;
-
-        if let Some(art_names) = art_namespaces {
-            for n in &art_names {
-                if n.namespace == namespace.key() {
-                    if n.indexed {
-                        return Err(error!(ErrorCode::ArtifactStillCached));
-                    } else {
-                        let mut new_vec = vec![];
-                        for j in &art_names {
-                            if j.namespace != namespace.key() {
-                                new_vec.push(j)
-                            }
-                        }
-                        let new_name = NamespaceAndIndex {
-                            namespace: anchor_lang::solana_program::system_program::id(),
-                            indexed: false,
-                            inherited: InheritanceState::NotInherited,
-                        };
-                        new_vec.push(&new_name);
-                        let mut data = artifact.data.borrow_mut();
-                        data[8] = 1; // Option yes.
-                        let mut start: usize = 0;
-                        for j in 0..new_vec.len() {
-                            let as_vec = new_vec[j].try_to_vec()?;
-                            for byte in as_vec {
-                                data[13 + start] = byte;
-                                start += 1;
-                            }
-                        }
-                        namespace.artifacts_added = namespace
-                            .artifacts_added
-                            .checked_sub(1)
-                            .ok_or(ErrorCode::NumericalOverflowError)?;
-                        return Ok(());
-                    }
-                }
-            }
-        }
+        let artifact_type = get_artifact_type(artifact)?;
+        remove_artifact_from_namespace(artifact, namespace)?;
+        close_membership(
+            &ctx.accounts.membership.to_account_info(),
+            &ctx.accounts.receiver.to_account_info(),
+        )?;
+        count_namespace_stat(CountNamespaceStatArgs {
+            namespace,
+            stat: NamespaceStat::Leave { artifact_type },
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
 
-        return Err(error!(ErrorCode::ArtifactNotPartOfNamespace));
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: Some(artifact.key()),
+            actor: None,
+            action: NamespaceAction::Leave,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
     }
 
     pub fn join_namespace<'info>(
         ctx: Context<'_, '_, '_, 'info, JoinNamespace<'info>>,
         _namespace_gatekeeper_bump: u8,
+        args: JoinNamespaceArgs,
     ) -> Result<()> {
+        let namespace_authority_signed = assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::AdmitArtifacts),
+        })
+        .is_ok();
+
+        if ctx.accounts.namespace.frozen {
+            return Err(error!(ErrorCode::NamespaceFrozen));
+        }
+
         let namespace_gatekeeper = &ctx.accounts.namespace_gatekeeper;
         let artifact = &mut ctx.accounts.artifact;
-        let token_holder = &ctx.accounts.token_holder;
         let namespace = &mut ctx.accounts.namespace;
 
-        let mut art_namespaces =
-            assert_can_add_to_namespace(artifact, token_holder, namespace, namespace_gatekeeper)?;
+        assert_namespace_gatekeeper(namespace, namespace_gatekeeper)?;
 
-        if let Some(art_names) = &mut art_namespaces {
-            let mut found = false;
-            for n in 0..art_names.len() {
-                if art_names[n].namespace == namespace.key() {
-                    found = true;
-                }
-            }
-            if !found {
-                let mut most_recent_zero = false;
-                let mut start: usize = 0;
-
-                for mut n in art_names {
-                    if n.namespace == anchor_lang::solana_program::system_program::id() {
-                        most_recent_zero = true;
-                        n.namespace = namespace.key();
-                        n.indexed = false;
-                        let mut data = artifact.data.borrow_mut();
-                        data[8] = 1; // option yes
-                        let as_vec = n.try_to_vec()?;
-                        for byte in as_vec {
-                            data[13 + start] = byte;
-                            start += 1;
-                        }
-
-                        namespace.artifacts_added = namespace
-                            .artifacts_added
-                            .checked_add(1)
-                            .ok_or(ErrorCode::NumericalOverflowError)?;
-                        break;
-                    }
-                    start += NAMESPACE_AND_INDEX_SIZE;
-                }
-                if !most_recent_zero {
-                    msg!("Out of space!");
-                    return Err(error!(ErrorCode::CannotJoinNamespace));
-                }
-            }
-        } else {
+        let evidence = FilterEvidence {
+            mint_proof: args.mint_proof.as_deref(),
+            remaining_accounts: ctx.remaining_accounts,
+        };
+        let art_namespaces = assert_can_add_to_namespace(
+            artifact,
+            namespace,
+            namespace_gatekeeper,
+            namespace_authority_signed,
+            &evidence,
+        )?;
+
+        let join_fee = get_fee_owed(
+            namespace,
+            artifact,
+            &art_namespaces,
+            namespace_gatekeeper,
+            &evidence,
+            |fee_config| fee_config.join_fee,
+        )?;
+
+        let mut joined = false;
+        let artifact_info = artifact.to_account_info();
+        let art_names = ArtifactNamespaces::load(&artifact_info)?;
+        if !art_names.is_allocated() {
             msg!("Out of space! You did not allocate any space for namespaces.");
             return Err(error!(ErrorCode::CannotJoinNamespace));
         }
+        if !art_names.contains(&namespace.key())? {
+            if art_names.add(namespace.key())?.is_none() {
+                msg!("Out of space!");
+                return Err(error!(ErrorCode::CannotJoinNamespace));
+            }
+            namespace.artifacts_added = namespace
+                .artifacts_added
+                .checked_add(1)
+                .ok_or(ErrorCode::NumericalOverflowError)?;
+            joined = true;
+        }
+
+        if joined {
+            let artifact_type = get_artifact_type(&artifact.to_account_info())?;
+            assert_within_namespace_limits(namespace, &artifact_type)?;
+
+            count_wallet_join(CountWalletJoinArgs {
+                namespace,
+                artifact: &artifact_info,
+                artifact_token_account: &ctx.accounts.artifact_token_account.to_account_info(),
+                wallet_quota: &ctx.accounts.wallet_quota.to_account_info(),
+                payer: &ctx.accounts.payer,
+                rent: &ctx.accounts.rent,
+                system_program: &ctx.accounts.system_program,
+            })?;
+            start_membership(StartMembershipArgs {
+                namespace,
+                artifact: artifact.key(),
+                membership: &ctx.accounts.membership.to_account_info(),
+                bump: *ctx.bumps.get("membership").unwrap(),
+                payer: &ctx.accounts.payer,
+                rent: &ctx.accounts.rent,
+                system_program: &ctx.accounts.system_program,
+                now: ctx.accounts.clock.unix_timestamp,
+            })?;
+            count_namespace_stat(CountNamespaceStatArgs {
+                namespace,
+                stat: NamespaceStat::Join { artifact_type },
+                remaining_accounts: ctx.remaining_accounts,
+            })?;
+        }
+
+        if joined && join_fee > 0 {
+            charge_namespace_fee(ChargeNamespaceFeeArgs {
+                namespace,
+                treasury: &ctx.accounts.treasury,
+                payer: &ctx.accounts.payer,
+                payer_token_account: &ctx.accounts.payer_token_account,
+                treasury_token_account: &ctx.accounts.treasury_token_account,
+                token_program: &ctx.accounts.token_program,
+                system_program: &ctx.accounts.system_program,
+                amount: join_fee,
+            })?;
+        }
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: Some(artifact.key()),
+            actor: Some(ctx.accounts.payer.key()),
+            action: NamespaceAction::Join,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
         Ok(())
     }
 
-    pub fn item_validation<'info>(
-        _ctx: Context<'_, '_, '_, 'info, ItemValidation<'info>>,
-        _args: ValidationArgs,
+    /// SOL withdrawals always leave the treasury rent exempt. SPL fees go to an existing
+    /// token account of the fee mint.
+    pub fn withdraw_namespace_fees<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawNamespaceFees<'info>>,
+        args: WithdrawNamespaceFeesArgs,
     ) -> Result<()> {
-        // Dummy
+        let WithdrawNamespaceFeesArgs { fee_mint, amount } = args;
+
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
+        let namespace_key = ctx.accounts.namespace.key();
+        let treasury = &ctx.accounts.treasury;
+        let receiver = &ctx.accounts.receiver;
+        let signer_seeds = [
+            PREFIX.as_bytes(),
+            namespace_key.as_ref(),
+            TREASURY.as_bytes(),
+            &[*ctx.bumps.get("treasury").unwrap()],
+        ];
+
+        if let Some(mint) = fee_mint {
+            let treasury_token_account = &ctx.accounts.treasury_token_account;
+            if treasury_token_account.key()
+                != spl_associated_token_account::get_associated_token_address(
+                    &treasury.key(),
+                    &mint,
+                )
+            {
+                return Err(error!(ErrorCode::InvalidTreasuryTokenAccount));
+            }
+            assert_owned_by(receiver, &spl_token::id())?;
+            let receiver_token: spl_token::state::Account =
+                assert_initialized(&receiver.to_account_info())?;
+            if receiver_token.mint != mint {
+                return Err(error!(ErrorCode::InvalidFeeReceiverTokenAccount));
+            }
+            spl_token_transfer(TokenTransferParams {
+                source: treasury_token_account.to_account_info(),
+                destination: receiver.to_account_info(),
+                amount,
+                authority: treasury.to_account_info(),
+                authority_signer_seeds: &signer_seeds,
+                token_program: ctx.accounts.token_program.to_account_info(),
+            })?;
+        } else {
+            let left = treasury
+                .lamports()
+                .checked_sub(amount)
+                .ok_or(ErrorCode::TreasuryBelowRent)?;
+            if left < Rent::get()?.minimum_balance(0) {
+                return Err(error!(ErrorCode::TreasuryBelowRent));
+            }
+            invoke_signed(
+                &system_instruction::transfer(&treasury.key(), &receiver.key(), amount),
+                &[
+                    treasury.to_account_info(),
+                    receiver.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                &[&signer_seeds],
+            )?;
+        }
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::WithdrawFees,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
         Ok(())
     }
 
-    pub fn match_validation<'info>(
-        _ctx: Context<'_, '_, '_, 'info, MatchValidation<'info>>,
-        _args: MatchValidationArgs,
+    /// Namespaces are grown right here. Item classes, items and matches can only be
+    /// realloced by the program that owns them, so for those this calls that program's own
+    /// grow_namespace_array, which has to be passed in remaining accounts.
+    pub fn grow_namespace_array<'info>(
+        ctx: Context<'_, '_, '_, 'info, GrowNamespaceArray<'info>>,
+        args: GrowNamespaceArrayArgs,
+    ) -> Result<()> {
+        let artifact = &ctx.accounts.artifact;
+
+        if artifact.owner == ctx.program_id {
+            return utils::grow_namespace_array(GrowNamespaceArrayParams {
+                artifact: &artifact.to_account_info(),
+                payer: &ctx.accounts.payer.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+                rent: &ctx.accounts.rent.to_account_info(),
+                additional_slots: args.additional_namespace_slots as usize,
+            });
+        }
+
+        // The player program has no instructions yet, so there is nothing to forward to.
+        if artifact.owner != &Pubkey::from_str(ITEM_ID).unwrap()
+            && artifact.owner != &Pubkey::from_str(MATCH_ID).unwrap()
+        {
+            return Err(error!(ErrorCode::CanOnlyResizeValidRaindropsObjects));
+        }
+        let artifact_program = ctx
+            .remaining_accounts
+            .iter()
+            .find(|acct| acct.key == artifact.owner)
+            .ok_or(ErrorCode::ArtifactProgramMissing)?;
+
+        let mut data = hash(b"global:grow_namespace_array").to_bytes()[..8].to_vec();
+        data.extend(args.try_to_vec()?);
+        invoke(
+            &Instruction {
+                program_id: *artifact.owner,
+                accounts: vec![
+                    AccountMeta::new(artifact.key(), false),
+                    AccountMeta::new(ctx.accounts.payer.key(), true),
+                    AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
+                    AccountMeta::new_readonly(ctx.accounts.rent.key(), false),
+                ],
+                data,
+            },
+            &[
+                artifact.to_account_info(),
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.rent.to_account_info(),
+                artifact_program.clone(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Brings a namespace created before the authority, delegates, fees and the rest were
+    /// added up to the size initialize_namespace gives one today. Those fields only had the
+    /// old padding, which a namespace with a full staking whitelist does not have enough of
+    /// to even be loaded. Anyone may pay for it.
+    pub fn resize_namespace<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResizeNamespace<'info>>,
+    ) -> Result<()> {
+        let namespace = &ctx.accounts.namespace.to_account_info();
+        if namespace.owner != ctx.program_id {
+            return Err(error!(ErrorCode::CanOnlyResizeValidRaindropsObjects));
+        }
+        let namespaces = ArtifactNamespaces::load(namespace)
+            .map_err(|_| error!(ErrorCode::CanOnlyResizeValidRaindropsObjects))?;
+
+        let new_len = MIN_NAMESPACE_SIZE + 4 + namespaces.len() * NAMESPACE_AND_INDEX_SIZE;
+        if new_len > namespace.data_len() {
+            utils::realloc_with_rent(ReallocWithRentParams {
+                account: namespace,
+                payer: &ctx.accounts.payer.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+                rent: &ctx.accounts.rent.to_account_info(),
+                new_len,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn create_namespace_multisig<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateNamespaceMultisig<'info>>,
+        args: NamespaceMultisigArgs,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: None,
+        })?;
+
+        let NamespaceMultisigArgs { threshold, signers } = args;
+        assert_valid_multisig(threshold, &signers)?;
+
+        let namespace_multisig = &mut ctx.accounts.namespace_multisig;
+        namespace_multisig.namespace = ctx.accounts.namespace.key();
+        namespace_multisig.bump = *ctx.bumps.get("namespace_multisig").unwrap();
+        namespace_multisig.threshold = threshold;
+        namespace_multisig.signers = signers;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::CreateMultisig,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    pub fn update_namespace_multisig<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpdateNamespaceMultisig<'info>>,
+        args: NamespaceMultisigArgs,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: None,
+        })?;
+
+        let NamespaceMultisigArgs { threshold, signers } = args;
+        assert_valid_multisig(threshold, &signers)?;
+
+        let namespace_multisig = &mut ctx.accounts.namespace_multisig;
+        namespace_multisig.threshold = threshold;
+        namespace_multisig.signers = signers;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::UpdateMultisig,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    pub fn set_namespace_authority<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetNamespaceAuthority<'info>>,
+        args: SetNamespaceAuthorityArgs,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: None,
+        })?;
+
+        // Setting None hands control back to whoever holds the namespace token.
+        ctx.accounts.namespace.authority = args.authority;
+
+        fit_namespace_to_data(FitNamespaceToDataArgs {
+            namespace: &ctx.accounts.namespace,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::SetAuthority,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    pub fn set_namespace_delegates<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetNamespaceAuthority<'info>>,
+        args: SetNamespaceDelegatesArgs,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: None,
+        })?;
+
+        if args.delegates.len() > MAX_DELEGATES {
+            return Err(error!(ErrorCode::TooManyDelegates));
+        }
+        for delegate in &args.delegates {
+            if delegate.powers.len() > MAX_DELEGATE_POWERS {
+                return Err(error!(ErrorCode::TooManyDelegates));
+            }
+        }
+
+        ctx.accounts.namespace.delegates = args.delegates;
+
+        fit_namespace_to_data(FitNamespaceToDataArgs {
+            namespace: &ctx.accounts.namespace,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::SetDelegates,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    pub fn set_namespace_parent<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetNamespaceParent<'info>>,
+        args: SetNamespaceParentArgs,
+    ) -> Result<()> {
+        let SetNamespaceParentArgs {
+            inherit_permissiveness_settings,
+            inherit_whitelisted_staking_mints,
+            inherit_gatekeeper,
+        } = args;
+
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
+        let namespace = &mut ctx.accounts.namespace;
+        let parent = &ctx.accounts.parent;
+
+        // Inheriting nothing turns sub-namespace mode back off.
+        if !inherit_permissiveness_settings
+            && !inherit_whitelisted_staking_mints
+            && !inherit_gatekeeper
+        {
+            namespace.inheritance = None;
+            fit_namespace_to_data(FitNamespaceToDataArgs {
+                namespace,
+                payer: &ctx.accounts.payer,
+                system_program: &ctx.accounts.system_program,
+                rent: &ctx.accounts.rent,
+            })?;
+
+            record_namespace_activity(RecordNamespaceActivityArgs {
+                namespace,
+                artifact: None,
+                actor: Some(ctx.accounts.token_holder.key()),
+                action: NamespaceAction::SetParent,
+                remaining_accounts: ctx.remaining_accounts,
+            })?;
+            return Ok(());
+        }
+
+        assert_child_of_namespace(namespace, parent)?;
+
+        let state = |inherit: bool| {
+            if inherit {
+                InheritanceState::Inherited
+            } else {
+                InheritanceState::NotInherited
+            }
+        };
+        namespace.inheritance = Some(NamespaceInheritance {
+            parent: parent.key(),
+            permissiveness_settings: state(inherit_permissiveness_settings),
+            whitelisted_staking_mints: state(inherit_whitelisted_staking_mints),
+            gatekeeper: state(inherit_gatekeeper),
+        });
+        propagate_namespace_parent(namespace, parent);
+
+        fit_namespace_to_data(FitNamespaceToDataArgs {
+            namespace,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::SetParent,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
+    }
+
+    pub fn update_namespace_inheritance<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpdateNamespaceInheritance<'info>>,
+    ) -> Result<()> {
+        let namespace = &mut ctx.accounts.namespace;
+        let parent = &ctx.accounts.parent;
+
+        match &namespace.inheritance {
+            Some(inheritance) if inheritance.parent == parent.key() => {}
+            _ => return Err(error!(ErrorCode::NotParentNamespace)),
+        }
+        assert_child_of_namespace(namespace, parent)?;
+
+        propagate_namespace_parent(namespace, parent);
+
+        fit_namespace_to_data(FitNamespaceToDataArgs {
+            namespace,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace,
+            artifact: None,
+            actor: None,
+            action: NamespaceAction::UpdateInheritance,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+
+        Ok(())
+    }
+
+    pub fn create_namespace_validator<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateNamespaceValidator<'info>>,
+        args: NamespaceValidatorArgs,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
+        // Zero is what callbacks use when they want no validation at all.
+        if args.code == 0 {
+            return Err(error!(ErrorCode::ReservedValidatorCode));
+        }
+
+        let validator = &mut ctx.accounts.validator;
+        validator.namespace = ctx.accounts.namespace.key();
+        validator.code = args.code;
+        validator.bump = *ctx.bumps.get("validator").unwrap();
+        validator.require_membership = args.require_membership;
+        validator.max_activations = args.max_activations;
+        validator.require_pass = args.require_pass;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::CreateValidator,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    pub fn update_namespace_validator<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpdateNamespaceValidator<'info>>,
+        args: NamespaceValidatorArgs,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
+        let validator = &mut ctx.accounts.validator;
+        validator.require_membership = args.require_membership;
+        validator.max_activations = args.max_activations;
+        validator.require_pass = args.require_pass;
+
+        record_namespace_activity(RecordNamespaceActivityArgs {
+            namespace: &ctx.accounts.namespace,
+            artifact: None,
+            actor: Some(ctx.accounts.token_holder.key()),
+            action: NamespaceAction::UpdateValidator,
+            remaining_accounts: ctx.remaining_accounts,
+        })?;
+        Ok(())
+    }
+
+    pub fn create_namespace_activity_log<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateNamespaceActivityLog<'info>>,
     ) -> Result<()> {
-        // Dummy
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
+        let activity_log = &mut ctx.accounts.activity_log;
+        activity_log.namespace = ctx.accounts.namespace.key();
+        activity_log.bump = *ctx.bumps.get("activity_log").unwrap();
+        // From here on instructions on this namespace write to the log whenever it is passed.
+        ctx.accounts.namespace.activity_log = Some(activity_log.key());
+
+        fit_namespace_to_data(FitNamespaceToDataArgs {
+            namespace: &ctx.accounts.namespace,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
         Ok(())
     }
+
+    pub fn create_namespace_stats<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateNamespaceStats<'info>>,
+    ) -> Result<()> {
+        assert_namespace_authority(AssertNamespaceAuthorityArgs {
+            namespace: &ctx.accounts.namespace,
+            namespace_token: &ctx.accounts.namespace_token,
+            token_holder: &ctx.accounts.token_holder.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+            power: Some(NamespaceAdminPower::UpdateNamespace),
+        })?;
+
+        let namespace_stats = &mut ctx.accounts.namespace_stats;
+        namespace_stats.namespace = ctx.accounts.namespace.key();
+        namespace_stats.bump = *ctx.bumps.get("namespace_stats").unwrap();
+        // Like the activity log, from here on joins and leaves count whenever passed the stats.
+        ctx.accounts.namespace.stats = Some(namespace_stats.key());
+
+        fit_namespace_to_data(FitNamespaceToDataArgs {
+            namespace: &ctx.accounts.namespace,
+            payer: &ctx.accounts.payer,
+            system_program: &ctx.accounts.system_program,
+            rent: &ctx.accounts.rent,
+        })?;
+        Ok(())
+    }
+
+    /// Called by the item, matches and staking programs to count what happens to artifacts in
+    /// this namespace. Each program signs with its ['stats_authority'] PDA, and may only record
+    /// its own stats. Joins and leaves are counted by this program.
+    pub fn record_namespace_stat<'info>(
+        ctx: Context<'_, '_, '_, 'info, RecordNamespaceStat<'info>>,
+        stat: NamespaceStat,
+    ) -> Result<()> {
+        let program = match stat {
+            NamespaceStat::ItemActivated | NamespaceStat::CraftCompleted => ITEM_ID,
+            NamespaceStat::MatchPlayed => MATCH_ID,
+            NamespaceStat::TokensStaked { .. } => STAKING_ID,
+            NamespaceStat::Join { .. } | NamespaceStat::Leave { .. } => {
+                return Err(error!(ErrorCode::StatNotRecordableByProgram))
+            }
+        };
+        let (stats_authority, _) = Pubkey::find_program_address(
+            &[STATS_AUTHORITY.as_bytes()],
+            &Pubkey::from_str(program).unwrap(),
+        );
+        if ctx.accounts.stats_authority.key() != stats_authority {
+            return Err(error!(ErrorCode::StatNotRecordableByProgram));
+        }
+
+        if !is_part_of_namespace(&ctx.accounts.artifact, &ctx.accounts.namespace.key()) {
+            return Err(error!(ErrorCode::ArtifactNotPartOfNamespace));
+        }
+
+        ctx.accounts
+            .namespace_stats
+            .record(&stat, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    pub fn item_validation<'info>(
+        ctx: Context<'_, '_, '_, 'info, ItemValidation<'info>>,
+        args: ValidationArgs,
+    ) -> Result<()> {
+        if args.extra_identifier == 0 {
+            return Ok(());
+        }
+
+        let item_account: spl_token::state::Account =
+            assert_initialized(&ctx.accounts.item_account)?;
+
+        validate_with_namespace_validator(ValidateWithNamespaceValidatorArgs {
+            extra_identifier: args.extra_identifier,
+            // The item class is where the usage callback was set.
+            scope: &ctx.accounts.item_class,
+            artifact: &ctx.accounts.item,
+            wallet: item_account.owner,
+            usage_index: Some(args.usage_index),
+            remaining_accounts: ctx.remaining_accounts,
+        })
+    }
+
+    pub fn match_validation<'info>(
+        ctx: Context<'_, '_, '_, 'info, MatchValidation<'info>>,
+        args: MatchValidationArgs,
+    ) -> Result<()> {
+        if args.extra_identifier == 0 {
+            return Ok(());
+        }
+
+        let source_token_account: spl_token::state::Account =
+            assert_initialized(&ctx.accounts.source_token_account)?;
+        if source_token_account.mint != ctx.accounts.mint.key() {
+            return Err(error!(ErrorCode::SourceTokenAccountMintMismatch));
+        }
+
+        validate_with_namespace_validator(ValidateWithNamespaceValidatorArgs {
+            extra_identifier: args.extra_identifier,
+            // The match itself is not passed to callbacks, so it is the artifact entering it.
+            scope: &ctx.accounts.source_item_or_player_pda,
+            artifact: &ctx.accounts.source_item_or_player_pda,
+            wallet: source_token_account.owner,
+            usage_index: None,
+            remaining_accounts: ctx.remaining_accounts,
+        })
+    }
+}
+
+struct ValidateWithNamespaceValidatorArgs<'a, 'info> {
+    extra_identifier: u64,
+    // The validator must belong to one of this artifact's namespaces.
+    scope: &'a AccountInfo<'info>,
+    artifact: &'a AccountInfo<'info>,
+    wallet: Pubkey,
+    // Only items have usages to count activations against.
+    usage_index: Option<u16>,
+    remaining_accounts: &'a [AccountInfo<'info>],
+}
+
+/// Runs the checks of the NamespaceValidator selected by extra_identifier in one of the scope's
+/// namespaces. The validator, and for pass checks the pass artifact and the wallet's token
+/// account for it, can be anywhere in remaining accounts since the calling program may have its
+/// own accounts in there too.
+fn validate_with_namespace_validator(args: ValidateWithNamespaceValidatorArgs) -> Result<()> {
+    let ValidateWithNamespaceValidatorArgs {
+        extra_identifier,
+        scope,
+        artifact,
+        wallet,
+        usage_index,
+        remaining_accounts,
+    } = args;
+
+    // Codes are only unique within a namespace, so the validator has to come from one of
+    // the namespaces of whatever chose the callback.
+    let validator_info = pull_namespaces(scope)?
+        .unwrap_or_default()
+        .iter()
+        .filter(|n| n.namespace != anchor_lang::solana_program::system_program::id())
+        .find_map(|n| {
+            let (validator_key, _) = Pubkey::find_program_address(
+                &[
+                    PREFIX.as_bytes(),
+                    n.namespace.as_ref(),
+                    VALIDATOR.as_bytes(),
+                    &extra_identifier.to_le_bytes(),
+                ],
+                &crate::id(),
+            );
+            remaining_accounts
+                .iter()
+                .find(|acct| acct.key == &validator_key)
+        })
+        .ok_or(ErrorCode::NamespaceValidatorMissing)?;
+    let validator: Account<NamespaceValidator> = Account::try_from(validator_info)?;
+
+    if validator.require_membership && !is_part_of_namespace(artifact, &validator.namespace) {
+        return Err(error!(ErrorCode::ArtifactNotPartOfNamespace));
+    }
+
+    if let Some(max_activations) = validator.max_activations {
+        let usage_index = usage_index.ok_or(ErrorCode::ActivationLimitOnlyAppliesToItems)?;
+        let uses = read_item_usage_uses(artifact, usage_index)?.unwrap_or(0);
+        if uses >= max_activations {
+            return Err(error!(ErrorCode::ActivationLimitReached));
+        }
+    }
+
+    if validator.require_pass {
+        // The artifact being validated can't be its own pass.
+        let artifact_mint = read_artifact_mint(artifact)?;
+        let mut holds_pass = false;
+        for token_info in remaining_accounts {
+            if token_info.owner != &spl_token::id()
+                || token_info.data_len() != spl_token::state::Account::LEN
+            {
+                continue;
+            }
+            let token_account: spl_token::state::Account = assert_initialized(token_info)?;
+            if token_account.owner != wallet
+                || token_account.amount == 0
+                || Some(token_account.mint) == artifact_mint
+            {
+                continue;
+            }
+            for pass in remaining_accounts {
+                if is_part_of_namespace(pass, &validator.namespace)
+                    && read_artifact_mint(pass)? == Some(token_account.mint)
+                {
+                    holds_pass = true;
+                    break;
+                }
+            }
+            if holds_pass {
+                break;
+            }
+        }
+        if !holds_pass {
+            return Err(error!(ErrorCode::NamespacePassNotHeld));
+        }
+    }
+
+    Ok(())
+}
+
+struct CountWalletJoinArgs<'a, 'info> {
+    namespace: &'a Account<'info, Namespace>,
+    artifact: &'a AccountInfo<'info>,
+    artifact_token_account: &'a AccountInfo<'info>,
+    wallet_quota: &'a AccountInfo<'info>,
+    payer: &'a Signer<'info>,
+    rent: &'a Sysvar<'info, Rent>,
+    system_program: &'a Program<'info, System>,
+}
+
+/// Counts a join against the quota of the wallet holding the artifact, creating it on their
+/// first join. Does nothing unless the namespace caps joins per wallet.
+///
+/// Joins are counted for life, leaving or expiring does not hand the slot back. By then the
+/// artifact may have changed hands, and the wallet it was counted against is not recorded.
+fn count_wallet_join(args: CountWalletJoinArgs) -> Result<()> {
+    let CountWalletJoinArgs {
+        namespace,
+        artifact,
+        artifact_token_account,
+        wallet_quota,
+        payer,
+        rent,
+        system_program,
+    } = args;
+
+    let max_per_wallet = match namespace.limits.as_ref().and_then(|lim| lim.max_per_wallet) {
+        Some(max_per_wallet) => max_per_wallet,
+        None => return Ok(()),
+    };
+
+    assert_owned_by(artifact_token_account, &spl_token::id())?;
+    let token_account: spl_token::state::Account = assert_initialized(artifact_token_account)?;
+    match read_artifact_mint(artifact)? {
+        Some(mint) if mint == token_account.mint && token_account.amount > 0 => {}
+        _ => return Err(error!(ErrorCode::ArtifactNotHeld)),
+    }
+    let wallet = token_account.owner;
+
+    let namespace_key = namespace.key();
+    let bump = assert_derivation(
+        &crate::id(),
+        wallet_quota,
+        &[
+            PREFIX.as_bytes(),
+            namespace_key.as_ref(),
+            wallet.as_ref(),
+            QUOTA.as_bytes(),
+        ],
+    )?;
+    if wallet_quota.data_is_empty() {
+        let signer_seeds = [
+            PREFIX.as_bytes(),
+            namespace_key.as_ref(),
+            wallet.as_ref(),
+            QUOTA.as_bytes(),
+            &[bump],
+        ];
+        create_or_allocate_account_raw(
+            crate::id(),
+            wallet_quota,
+            &rent.to_account_info(),
+            system_program,
+            payer,
+            WALLET_QUOTA_SIZE,
+            &signer_seeds,
+        )?;
+    }
+    let mut quota: Account<'_, NamespaceWalletQuota> = Account::try_from_unchecked(wallet_quota)?;
+    quota.namespace = namespace_key;
+    quota.wallet = wallet;
+    quota.bump = bump;
+    quota.artifacts_joined = quota
+        .artifacts_joined
+        .checked_add(1)
+        .ok_or(ErrorCode::NumericalOverflowError)?;
+    if quota.artifacts_joined > max_per_wallet {
+        return Err(error!(ErrorCode::WalletJoinLimitReached));
+    }
+    quota.exit(&crate::id())?;
+    Ok(())
+}
+
+struct StartMembershipArgs<'a, 'info> {
+    namespace: &'a Account<'info, Namespace>,
+    artifact: Pubkey,
+    membership: &'a AccountInfo<'info>,
+    bump: u8,
+    payer: &'a Signer<'info>,
+    rent: &'a Sysvar<'info, Rent>,
+    system_program: &'a Program<'info, System>,
+    now: i64,
+}
+
+/// Starts the clock on a new member. Does nothing unless the namespace has a membership
+/// duration, apart from marking a record left behind by an earlier membership as never
+/// expiring so it cannot be used to expire the artifact later on.
+fn start_membership(args: StartMembershipArgs) -> Result<()> {
+    let StartMembershipArgs {
+        namespace,
+        artifact,
+        membership,
+        bump,
+        payer,
+        rent,
+        system_program,
+        now,
+    } = args;
+
+    let duration = match namespace.membership_duration {
+        Some(duration) => Some(duration),
+        None if membership.data_is_empty() => return Ok(()),
+        None => None,
+    };
+
+    let namespace_key = namespace.key();
+    // A record left behind by an earlier membership is reused, but its rent still belongs
+    // to whoever paid for it.
+    let created = membership.data_is_empty();
+    if created {
+        let signer_seeds = [
+            PREFIX.as_bytes(),
+            namespace_key.as_ref(),
+            artifact.as_ref(),
+            MEMBERSHIP.as_bytes(),
+            &[bump],
+        ];
+        create_or_allocate_account_raw(
+            crate::id(),
+            membership,
+            &rent.to_account_info(),
+            system_program,
+            payer,
+            MEMBERSHIP_SIZE,
+            &signer_seeds,
+        )?;
+    }
+    let mut record: Account<'_, NamespaceMembership> = Account::try_from_unchecked(membership)?;
+    record.namespace = namespace_key;
+    record.artifact = artifact;
+    if created {
+        record.payer = payer.key();
+    }
+    record.bump = bump;
+    record.expires_at = match duration {
+        Some(duration) => (now as u64)
+            .checked_add(duration)
+            .ok_or(ErrorCode::NumericalOverflowError)?,
+        None => NEVER_EXPIRES,
+    };
+    record.exit(&crate::id())?;
+    Ok(())
+}
+
+/// Closes a membership record if there is one. Its rent goes back to whoever paid for it.
+fn close_membership<'info>(
+    membership: &AccountInfo<'info>,
+    receiver: &AccountInfo<'info>,
+) -> Result<()> {
+    if membership.data_is_empty() {
+        return Ok(());
+    }
+    let record: Account<'_, NamespaceMembership> = Account::try_from(membership)?;
+    if record.payer != receiver.key() {
+        return Err(error!(ErrorCode::ReceiverMustBeMembershipPayer));
+    }
+    record.close(receiver.clone())
+}
+
+struct UncacheFromIndexPageArgs<'a, 'info> {
+    namespace: &'a mut Account<'info, Namespace>,
+    artifact: &'a AccountInfo<'info>,
+    index: &'a AccountInfo<'info>,
+    page: u64,
+    artifact_type: &'a ArtifactType,
+    receiver: &'a AccountInfo<'info>,
+}
+
+/// Takes a cached artifact off of an index page, closing the page if it was the highest
+/// one for its type, is now empty and the receiver paid for it.
+fn uncache_from_index_page(args: UncacheFromIndexPageArgs) -> Result<()> {
+    let UncacheFromIndexPageArgs {
+        namespace,
+        artifact,
+        index,
+        page,
+        artifact_type,
+        receiver,
+    } = args;
+
+    if get_artifact_type(artifact)? != *artifact_type {
+        return Err(error!(ErrorCode::ArtifactTypeMismatch));
+    }
+    let namespace_key = namespace.key();
+    let page_str = page.to_string();
+    assert_derivation(
+        &crate::id(),
+        index,
+        &[
+            PREFIX.as_bytes(),
+            namespace_key.as_ref(),
+            artifact_type.get_seed().as_bytes(),
+            page_str.as_bytes(),
+        ],
+    )?;
+    let mut index_page: Account<'_, NamespaceIndex> = Account::try_from(index)?;
+    let cache_count = index_page.caches.len();
+    index_page.caches.retain(|c| c.key != artifact.key());
+    if index_page.caches.len() == cache_count {
+        return Err(error!(ErrorCode::NotCachedHere));
+    }
+
+    set_indexed_bool_for_namespace(artifact, namespace_key, false)?;
+    namespace.artifacts_cached = namespace
+        .artifacts_cached
+        .checked_sub(1)
+        .ok_or(ErrorCode::NumericalOverflowError)?;
+
+    // The receiver here is the membership's payer, so a page someone else paid for is left
+    // open, empty, to be reused or closed by uncache_artifact or compact_namespace_index.
+    let type_index = artifact_type.get_index();
+    if page == namespace.highest_page_by_type[type_index]
+        && index_page.caches.is_empty()
+        && receiver.key() == index_page.payer
+    {
+        // Page 0 stays as the head of the list even when empty.
+        if page > 0 {
+            namespace.highest_page_by_type[type_index] = page
+                .checked_sub(1)
+                .ok_or(ErrorCode::NumericalOverflowError)?;
+        }
+        close_index_page(index, receiver)?;
+    } else {
+        index_page.exit(&crate::id())?;
+    }
+    Ok(())
+}
+
+fn assert_gatekeeper_filter_index(
+    namespace_gatekeeper: &NamespaceGatekeeper,
+    idx: u64,
+) -> Result<usize> {
+    let idx = idx as usize;
+    if idx >= namespace_gatekeeper.artifact_filters.len() {
+        return Err(error!(ErrorCode::GatekeeperFilterIndexOutOfRange));
+    }
+    Ok(idx)
+}
+
+struct FitGatekeeperToFiltersArgs<'a, 'info> {
+    namespace_gatekeeper: &'a Account<'info, NamespaceGatekeeper>,
+    payer: &'a Signer<'info>,
+    system_program: &'a Program<'info, System>,
+    rent: &'a Sysvar<'info, Rent>,
+}
+
+/// Grows the gatekeeper account if its filters no longer fit. Gatekeepers are never shrunk,
+/// so the space freed by removing a filter is there for the next one.
+fn fit_gatekeeper_to_filters(args: FitGatekeeperToFiltersArgs) -> Result<()> {
+    let FitGatekeeperToFiltersArgs {
+        namespace_gatekeeper,
+        payer,
+        system_program,
+        rent,
+    } = args;
+
+    let needed = 8 + namespace_gatekeeper.try_to_vec()?.len();
+    let gatekeeper_info = namespace_gatekeeper.to_account_info();
+    if needed > gatekeeper_info.data_len() {
+        utils::realloc_with_rent(ReallocWithRentParams {
+            account: &gatekeeper_info,
+            payer: &payer.to_account_info(),
+            system_program: &system_program.to_account_info(),
+            rent: &rent.to_account_info(),
+            new_len: needed,
+        })?;
+    }
+    Ok(())
+}
+
+struct FitNamespaceToDataArgs<'a, 'info> {
+    namespace: &'a Account<'info, Namespace>,
+    payer: &'a Signer<'info>,
+    system_program: &'a Program<'info, System>,
+    rent: &'a Sysvar<'info, Rent>,
+}
+
+/// Grows the namespace if its fields no longer fit. Namespaces created before authority,
+/// delegates, fees and the rest were added only have the old padding to hold them.
+fn fit_namespace_to_data(args: FitNamespaceToDataArgs) -> Result<()> {
+    let FitNamespaceToDataArgs {
+        namespace,
+        payer,
+        system_program,
+        rent,
+    } = args;
+
+    let needed = 8 + namespace.try_to_vec()?.len();
+    let namespace_info = namespace.to_account_info();
+    if needed > namespace_info.data_len() {
+        utils::realloc_with_rent(ReallocWithRentParams {
+            account: &namespace_info,
+            payer: &payer.to_account_info(),
+            system_program: &system_program.to_account_info(),
+            rent: &rent.to_account_info(),
+            new_len: needed,
+        })?;
+    }
+    Ok(())
+}
+
+struct RecordNamespaceActivityArgs<'a, 'info> {
+    namespace: &'a Account<'info, Namespace>,
+    artifact: Option<Pubkey>,
+    // None for permissionless instructions
+    actor: Option<Pubkey>,
+    action: NamespaceAction,
+    remaining_accounts: &'a [AccountInfo<'info>],
+}
+
+/// Emits a NamespaceActivity event, and if the namespace keeps an activity log and it was
+/// passed, writes it there too. The log can be anywhere in remaining accounts after whatever
+/// the instruction itself reads from them in order, like whitelist mints or the artifacts
+/// being compacted.
+fn record_namespace_activity(args: RecordNamespaceActivityArgs) -> Result<()> {
+    let RecordNamespaceActivityArgs {
+        namespace,
+        artifact,
+        actor,
+        action,
+        remaining_accounts,
+    } = args;
+
+    let slot = Clock::get()?.slot;
+    emit!(NamespaceActivity {
+        namespace: namespace.key(),
+        artifact,
+        actor,
+        slot,
+        action: action.clone(),
+    });
+
+    let activity_log_info = namespace.activity_log.and_then(|activity_log_key| {
+        remaining_accounts
+            .iter()
+            .find(|acct| acct.key == &activity_log_key)
+    });
+    if let Some(activity_log_info) = activity_log_info {
+        let mut activity_log: Account<NamespaceActivityLog> = Account::try_from(activity_log_info)?;
+        activity_log.record(NamespaceActivityEntry {
+            artifact,
+            actor,
+            slot,
+            action,
+        });
+        activity_log.exit(&crate::id())?;
+    }
+
+    Ok(())
+}
+
+struct CountNamespaceStatArgs<'a, 'info> {
+    namespace: &'a Account<'info, Namespace>,
+    stat: NamespaceStat,
+    remaining_accounts: &'a [AccountInfo<'info>],
+}
+
+/// Counts the stat if the namespace keeps stats and they were passed. The stats account can
+/// be anywhere in remaining accounts.
+fn count_namespace_stat(args: CountNamespaceStatArgs) -> Result<()> {
+    let CountNamespaceStatArgs {
+        namespace,
+        stat,
+        remaining_accounts,
+    } = args;
+
+    let stats_info = namespace.stats.and_then(|stats_key| {
+        remaining_accounts
+            .iter()
+            .find(|acct| acct.key == &stats_key)
+    });
+    if let Some(stats_info) = stats_info {
+        let mut namespace_stats: Account<NamespaceStats> = Account::try_from(stats_info)?;
+        namespace_stats.record(&stat, Clock::get()?.unix_timestamp);
+        namespace_stats.exit(&crate::id())?;
+    }
+
+    Ok(())
+}
+
+/// Frees up the artifact's slot for this namespace. Cached artifacts must be uncached first.
+fn remove_artifact_from_namespace(
+    artifact: &AccountInfo,
+    namespace: &mut Account<Namespace>,
+) -> Result<()> {
+    let art_names = ArtifactNamespaces::load(artifact)?;
+    let slot = art_names
+        .position(&namespace.key())?
+        .ok_or(ErrorCode::ArtifactNotPartOfNamespace)?;
+    if art_names.get(slot)?.indexed {
+        return Err(error!(ErrorCode::ArtifactStillCached));
+    }
+    art_names.remove(&namespace.key())?;
+
+    count_departure(namespace, &get_artifact_type(artifact)?)
+}
+
+fn count_departure(namespace: &mut Account<Namespace>, artifact_type: &ArtifactType) -> Result<()> {
+    namespace.artifacts_added = namespace
+        .artifacts_added
+        .checked_sub(1)
+        .ok_or(ErrorCode::NumericalOverflowError)?;
+    // Saturating because namespaces created before per type counts existed
+    // may have members that were never counted.
+    let type_index = artifact_type.get_index();
+    namespace.artifacts_added_by_type[type_index] =
+        namespace.artifacts_added_by_type[type_index].saturating_sub(1);
+    Ok(())
+}
+
+/// Puts a migrating artifact on the destination's index page if the page exists and has
+/// room, returning whether it did.
+struct OpenIndexPageArgs<'a, 'info> {
+    namespace: &'a Account<'info, Namespace>,
+    index: &'a AccountInfo<'info>,
+    prior_index: &'a AccountInfo<'info>,
+    artifact_type: &'a ArtifactType,
+    page: u64,
+    bump: u8,
+    payer: &'a Signer<'info>,
+    rent: &'a Sysvar<'info, Rent>,
+    system_program: &'a Program<'info, System>,
+}
+
+/// Creates the index page if it does not exist yet, returning whether it did. Page 0 has no
+/// prior page, for every other page the prior one has to be full.
+fn open_index_page(args: OpenIndexPageArgs) -> Result<bool> {
+    let OpenIndexPageArgs {
+        namespace,
+        index,
+        prior_index,
+        artifact_type,
+        page,
+        bump,
+        payer,
+        rent,
+        system_program,
+    } = args;
+
+    if !index.data_is_empty() {
+        return Ok(false);
+    }
+    if page > 0 {
+        if prior_index.data_is_empty() {
+            return Err(error!(
+                ErrorCode::PreviousIndexNeedsToExistBeforeCreatingThisOne
+            ));
+        }
+        let prior_index: Account<'_, NamespaceIndex> = Account::try_from(prior_index)?;
+        if prior_index.caches.len() < MAX_CACHED_ITEMS {
+            return Err(error!(ErrorCode::PreviousIndexNotFull));
+        }
+    }
+    let namespace_key = namespace.key();
+    let page_str = page.to_string();
+    let signer_seeds = [
+        PREFIX.as_bytes(),
+        namespace_key.as_ref(),
+        artifact_type.get_seed().as_bytes(),
+        page_str.as_bytes(),
+        &[bump],
+    ];
+    create_or_allocate_account_raw(
+        crate::id(),
+        index,
+        &rent.to_account_info(),
+        system_program,
+        payer,
+        INDEX_SIZE,
+        &signer_seeds,
+    )?;
+    Ok(true)
+}
+
+fn recache_on_index_page(
+    namespace: &mut Account<Namespace>,
+    artifact: &AccountInfo,
+    index: &AccountInfo,
+    artifact_type: &ArtifactType,
+) -> Result<bool> {
+    if index.data_is_empty() {
+        return Ok(false);
+    }
+    let mut index_page: Account<'_, NamespaceIndex> = Account::try_from(index)?;
+    if index_page.caches.len() >= MAX_CACHED_ITEMS {
+        return Ok(false);
+    }
+    if let Some(max_cached) = namespace.limits.as_ref().and_then(|lim| lim.max_cached) {
+        if namespace.artifacts_cached >= max_cached {
+            return Ok(false);
+        }
+    }
+
+    index_page.caches.push(CachedArtifact {
+        key: artifact.key(),
+        is_class: is_artifact_class(artifact),
+        artifact_type: artifact_type.clone(),
+    });
+    index_page.exit(&crate::id())?;
+    namespace.artifacts_cached = namespace
+        .artifacts_cached
+        .checked_add(1)
+        .ok_or(ErrorCode::NumericalOverflowError)?;
+    set_indexed_bool_for_namespace(artifact, namespace.key(), true)?;
+    Ok(true)
+}
+
+fn has_migration_permit(migration_permit: &AccountInfo) -> bool {
+    !migration_permit.data_is_empty()
+        && Account::<NamespaceMigrationPermit>::try_from(migration_permit).is_ok()
+}
+
+/// A child namespace must have joined its parent before it can inherit from it.
+fn assert_child_of_namespace(
+    namespace: &Account<Namespace>,
+    parent: &Account<Namespace>,
+) -> Result<()> {
+    if namespace.key() == parent.key() {
+        return Err(error!(ErrorCode::NotParentNamespace));
+    }
+    if let Some(ns) = &namespace.namespaces {
+        for n in ns {
+            if n.namespace == parent.key() {
+                return Ok(());
+            }
+        }
+    }
+    Err(error!(ErrorCode::NotParentNamespace))
+}
+
+/// Copies every inherited setting down from the parent. The gatekeeper is not copied,
+/// it is resolved to the parent's gatekeeper at join time instead.
+fn propagate_namespace_parent(namespace: &mut Account<Namespace>, parent: &Account<Namespace>) {
+    if let Some(inheritance) = namespace.inheritance.clone() {
+        if inheritance.permissiveness_settings == InheritanceState::Inherited {
+            namespace.permissiveness_settings = parent.permissiveness_settings.clone();
+        }
+        if inheritance.whitelisted_staking_mints == InheritanceState::Inherited {
+            namespace.whitelisted_staking_mints = parent.whitelisted_staking_mints.clone();
+        }
+    }
+}
+
+/// The gatekeeper is the namespace's own unless it inherits its parent's.
+fn assert_namespace_gatekeeper(
+    namespace: &Account<Namespace>,
+    gatekeeper: &AccountInfo,
+) -> Result<()> {
+    let gatekeeper_owner = match &namespace.inheritance {
+        Some(inheritance) if inheritance.gatekeeper == InheritanceState::Inherited => {
+            inheritance.parent
+        }
+        _ => namespace.key(),
+    };
+    assert_derivation(
+        &crate::id(),
+        gatekeeper,
+        &[
+            PREFIX.as_bytes(),
+            gatekeeper_owner.as_ref(),
+            GATEKEEPER.as_bytes(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Counts a newly joined artifact against its type and checks the namespace caps.
+/// Expects artifacts_added to already include the new artifact.
+fn assert_within_namespace_limits(
+    namespace: &mut Account<Namespace>,
+    artifact_type: &ArtifactType,
+) -> Result<()> {
+    let type_index = artifact_type.get_index();
+    namespace.artifacts_added_by_type[type_index] = namespace.artifacts_added_by_type[type_index]
+        .checked_add(1)
+        .ok_or(ErrorCode::NumericalOverflowError)?;
+
+    if let Some(limits) = &namespace.limits {
+        if let Some(max_artifacts) = limits.max_artifacts {
+            if namespace.artifacts_added > max_artifacts {
+                return Err(error!(ErrorCode::NamespaceArtifactLimitReached));
+            }
+        }
+        for type_limit in &limits.max_per_artifact_type {
+            if type_limit.artifact_type == *artifact_type
+                && namespace.artifacts_added_by_type[type_index] > type_limit.max
+            {
+                return Err(error!(ErrorCode::ArtifactTypeLimitReached));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Works out what an artifact owes for an action, zero if the namespace charges nothing
+/// or the artifact is whitelisted and the namespace exempts whitelisted artifacts.
+fn get_fee_owed<'a>(
+    namespace: &Account<'a, Namespace>,
+    artifact: &UncheckedAccount<'a>,
+    art_namespaces: &Option<Vec<NamespaceAndIndex>>,
+    namespace_gatekeeper: &UncheckedAccount<'a>,
+    evidence: &FilterEvidence<'_, 'a>,
+    fee_for_action: fn(&NamespaceFeeConfig) -> u64,
+) -> Result<u64> {
+    if let Some(fee_config) = &namespace.fee_config {
+        let fee = fee_for_action(fee_config);
+        if fee == 0 {
+            return Ok(0);
+        }
+        if !fee_config.whitelisted_artifacts_exempt {
+            return Ok(fee);
+        }
+        // Only passing a whitelist earns the exemption, matching a blacklist does not.
+        let artifact_type = get_artifact_type(&artifact.to_account_info())?;
+        if let Permissiveness::Whitelist = artifact_type_permissiveness(namespace, &artifact_type) {
+            if artifact_matches_gatekeeper(
+                artifact,
+                art_namespaces,
+                namespace_gatekeeper,
+                &artifact_type,
+                evidence,
+            )? {
+                msg!("Whitelisted, skipping fee");
+                return Ok(0);
+            }
+        }
+        return Ok(fee);
+    }
+    Ok(0)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -438,7 +2736,7 @@ pub enum Permissiveness {
     Namespace,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
 pub enum ArtifactType {
     Player,
     Item,
@@ -446,6 +2744,29 @@ pub enum ArtifactType {
     Namespace,
 }
 
+pub const ARTIFACT_TYPE_COUNT: usize = 4;
+
+impl ArtifactType {
+    pub fn get_index(&self) -> usize {
+        match self {
+            ArtifactType::Player => 0,
+            ArtifactType::Item => 1,
+            ArtifactType::Mission => 2,
+            ArtifactType::Namespace => 3,
+        }
+    }
+
+    /// Each artifact type gets its own sequence of index pages under this seed.
+    pub fn get_seed(&self) -> &'static str {
+        match self {
+            ArtifactType::Player => "player",
+            ArtifactType::Item => "item",
+            ArtifactType::Mission => "mission",
+            ArtifactType::Namespace => "namespace",
+        }
+    }
+}
+
 pub const MAX_FILTER_SLOTS: usize = 5;
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -463,6 +2784,18 @@ pub enum Filter {
         metadata: Pubkey,
         edition: Option<Pubkey>,
     },
+    /// Matches artifacts whose mint is a leaf of the tree, with leaves hashed as
+    /// keccak(0x00 || mint). Joining artifacts bring the proof. Since an artifact can always
+    /// leave the proof out, this is only useful in a whitelist.
+    MerkleMint {
+        root: [u8; 32],
+    },
+    /// Matches artifacts whose metaplex metadata has this verified collection. The metadata
+    /// account goes in remaining accounts. Like MerkleMint, leaving it out just fails the
+    /// match, so this is only useful in a whitelist.
+    Collection {
+        collection_mint: Pubkey,
+    },
 }
 
 pub const FILTER_SIZE: usize = (MAX_FILTER_SLOTS + 1) * 32;
@@ -489,6 +2822,69 @@ pub struct PermissivenessSettings {
     cache_permissiveness: Permissiveness,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum NamespaceAdminPower {
+    UpdateNamespace,
+    EditGatekeeper,
+    CacheArtifacts,
+    AdmitArtifacts,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NamespaceFeeConfig {
+    /// None means fees are paid in SOL.
+    pub fee_mint: Option<Pubkey>,
+    pub join_fee: u64,
+    pub cache_fee: u64,
+    /// Artifacts matching a gatekeeper filter for their artifact type pay nothing.
+    pub whitelisted_artifacts_exempt: bool,
+    pub renew_fee: u64,
+}
+
+pub const NAMESPACE_FEE_CONFIG_SIZE: usize = 33 + // fee mint
+8 + // join fee
+8 + // cache fee
+1 + // whitelisted artifacts exempt
+8; // renew fee
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ArtifactTypeLimit {
+    pub artifact_type: ArtifactType,
+    pub max: u64,
+}
+
+pub const MAX_ARTIFACT_TYPE_LIMITS: usize = ARTIFACT_TYPE_COUNT;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NamespaceLimits {
+    /// Cap on artifacts_added
+    pub max_artifacts: Option<u64>,
+    /// Cap on artifacts_cached
+    pub max_cached: Option<u64>,
+    pub max_per_artifact_type: Vec<ArtifactTypeLimit>,
+    /// Cap on how many artifacts a single holder can ever join, tracked in NamespaceWalletQuota.
+    pub max_per_wallet: Option<u64>,
+}
+
+pub const NAMESPACE_LIMITS_SIZE: usize = 9 + // max artifacts
+9 + // max cached
+4 + // number of artifact type limits
+9 * MAX_ARTIFACT_TYPE_LIMITS + // artifact type limits
+9; // max per wallet
+
+pub const MAX_DELEGATES: usize = 5;
+pub const MAX_DELEGATE_POWERS: usize = 4;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NamespaceDelegate {
+    pub key: Pubkey,
+    pub powers: Vec<NamespaceAdminPower>,
+}
+
+pub const NAMESPACE_DELEGATE_SIZE: usize = 32 + // key
+4 + // number of powers
+MAX_DELEGATE_POWERS; // powers
+
 /// seed ['namespace', namespace program, mint]
 #[account]
 pub struct Namespace {
@@ -499,22 +2895,398 @@ pub struct Namespace {
     pub uuid: String,
     pub pretty_name: String,
     pub artifacts_added: u64,
+    /// Highest of the index pages from before pages were split by artifact type, which
+    /// migrate_legacy_index_entry drains. New pages are tracked in highest_page_by_type.
     pub highest_page: u64,
     pub artifacts_cached: u64,
     pub permissiveness_settings: PermissivenessSettings,
     pub bump: u8,
     pub whitelisted_staking_mints: Vec<Pubkey>,
+    /// If set, replaces the namespace token holder as the admin of this namespace.
+    /// Can be a plain key or a NamespaceMultisig.
+    pub authority: Option<Pubkey>,
+    /// Keys that may perform some admin actions without being the authority.
+    pub delegates: Vec<NamespaceDelegate>,
+    /// Fees are collected in the treasury, seed ['namespace', namespace program, namespace, 'treasury']
+    pub fee_config: Option<NamespaceFeeConfig>,
+    pub limits: Option<NamespaceLimits>,
+    /// artifacts_added broken down by ArtifactType
+    pub artifacts_added_by_type: [u64; ARTIFACT_TYPE_COUNT],
+    /// Set when this namespace is a sub-namespace of a namespace it has joined.
+    pub inheritance: Option<NamespaceInheritance>,
+    /// Highest index page for each ArtifactType's page sequence
+    pub highest_page_by_type: [u64; ARTIFACT_TYPE_COUNT],
+    /// If set, memberships last this many seconds unless renewed.
+    pub membership_duration: Option<u64>,
+    /// Emergency stop. Frozen namespaces take no new members or caches, and the other
+    /// raindrops programs refuse to act on their artifacts.
+    pub frozen: bool,
+    pub activity_log: Option<Pubkey>,
+    pub stats: Option<Pubkey>,
 }
 
-/// seed ['namespace', namespace program, mint, page number]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum NamespaceAction {
+    Initialize,
+    Update,
+    Freeze,
+    Unfreeze,
+    Join,
+    Leave,
+    Cache,
+    Uncache,
+    Compact,
+    ExpireMembership,
+    RenewMembership,
+    CreateGatekeeper,
+    AddToGatekeeper,
+    RemoveFromGatekeeper,
+    WithdrawFees,
+    CreateMultisig,
+    UpdateMultisig,
+    SetAuthority,
+    SetDelegates,
+    SetParent,
+    UpdateInheritance,
+    CreateValidator,
+    UpdateValidator,
+    ReplaceInGatekeeper,
+    ReorderGatekeeper,
+    MigrateOut,
+    MigrateIn,
+}
+
+#[event]
+pub struct NamespaceActivity {
+    pub namespace: Pubkey,
+    pub artifact: Option<Pubkey>,
+    pub actor: Option<Pubkey>,
+    pub slot: u64,
+    pub action: NamespaceAction,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NamespaceActivityEntry {
+    pub artifact: Option<Pubkey>,
+    pub actor: Option<Pubkey>,
+    pub slot: u64,
+    pub action: NamespaceAction,
+}
+
+pub const ACTIVITY_LOG_CAPACITY: usize = 50;
+
+/// Ring buffer of the most recent activity on a namespace. Only instructions that were
+/// passed the log write to it, the NamespaceActivity event is emitted either way.
+/// seed ['namespace', namespace program, namespace, 'activity']
+#[account]
+pub struct NamespaceActivityLog {
+    pub namespace: Pubkey,
+    pub bump: u8,
+    /// Total entries ever recorded. Once the log is full the oldest entry is at
+    /// entries_recorded % ACTIVITY_LOG_CAPACITY.
+    pub entries_recorded: u64,
+    pub entries: Vec<NamespaceActivityEntry>,
+}
+
+impl NamespaceActivityLog {
+    pub fn record(&mut self, entry: NamespaceActivityEntry) {
+        if self.entries.len() < ACTIVITY_LOG_CAPACITY {
+            self.entries.push(entry);
+        } else {
+            let position = (self.entries_recorded % ACTIVITY_LOG_CAPACITY as u64) as usize;
+            self.entries[position] = entry;
+        }
+        self.entries_recorded = self.entries_recorded.wrapping_add(1);
+    }
+}
+
+pub const ACTIVITY_ENTRY_SIZE: usize = 33 + // artifact
+33 + // actor
+8 + // slot
+1; // action
+
+pub const ACTIVITY_LOG_SIZE: usize = 8 + // key
+32 + // namespace
+1 + // bump
+8 + // entries recorded
+4 + // number of entries
+ACTIVITY_ENTRY_SIZE * ACTIVITY_LOG_CAPACITY; // entries
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum NamespaceStat {
+    Join { artifact_type: ArtifactType },
+    Leave { artifact_type: ArtifactType },
+    ItemActivated,
+    CraftCompleted,
+    MatchPlayed,
+    TokensStaked { amount: u64 },
+}
+
+pub const STATS_BUCKET_COUNT: usize = 30;
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Everything counted on one day.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct NamespaceStatsBucket {
+    /// Days since the unix epoch
+    pub day: u64,
+    pub joins: u64,
+    pub leaves: u64,
+    pub items_activated: u64,
+    pub crafts_completed: u64,
+    pub matches_played: u64,
+    pub tokens_staked: u64,
+}
+
+pub const STATS_BUCKET_SIZE: usize = 8 * 7;
+
+/// Running totals for a namespace, kept up to date by this program and the item, matches and
+/// staking programs so dashboards only need to read one account.
+/// seed ['namespace', namespace program, namespace, 'stats']
+#[account]
+pub struct NamespaceStats {
+    pub namespace: Pubkey,
+    pub bump: u8,
+    /// Indexed by ArtifactType
+    pub joins_by_type: [u64; ARTIFACT_TYPE_COUNT],
+    pub leaves_by_type: [u64; ARTIFACT_TYPE_COUNT],
+    pub items_activated: u64,
+    pub crafts_completed: u64,
+    pub matches_played: u64,
+    pub tokens_staked: u64,
+    /// Rolling daily buckets, day d lives at d % STATS_BUCKET_COUNT. A bucket whose day is
+    /// not the one you asked for has not seen any activity that day.
+    pub daily: Vec<NamespaceStatsBucket>,
+}
+
+impl NamespaceStats {
+    pub fn record(&mut self, stat: &NamespaceStat, unix_timestamp: i64) {
+        // Stats never block the instruction recording them, so these saturate.
+        let day = (unix_timestamp / SECONDS_PER_DAY) as u64;
+        let position = (day % STATS_BUCKET_COUNT as u64) as usize;
+        if self.daily.len() <= position {
+            self.daily
+                .resize(position + 1, NamespaceStatsBucket::default());
+        }
+        let bucket = &mut self.daily[position];
+        if bucket.day != day {
+            *bucket = NamespaceStatsBucket {
+                day,
+                ..NamespaceStatsBucket::default()
+            };
+        }
+
+        match stat {
+            NamespaceStat::Join { artifact_type } => {
+                let type_index = artifact_type.get_index();
+                self.joins_by_type[type_index] = self.joins_by_type[type_index].saturating_add(1);
+                bucket.joins = bucket.joins.saturating_add(1);
+            }
+            NamespaceStat::Leave { artifact_type } => {
+                let type_index = artifact_type.get_index();
+                self.leaves_by_type[type_index] = self.leaves_by_type[type_index].saturating_add(1);
+                bucket.leaves = bucket.leaves.saturating_add(1);
+            }
+            NamespaceStat::ItemActivated => {
+                self.items_activated = self.items_activated.saturating_add(1);
+                bucket.items_activated = bucket.items_activated.saturating_add(1);
+            }
+            NamespaceStat::CraftCompleted => {
+                self.crafts_completed = self.crafts_completed.saturating_add(1);
+                bucket.crafts_completed = bucket.crafts_completed.saturating_add(1);
+            }
+            NamespaceStat::MatchPlayed => {
+                self.matches_played = self.matches_played.saturating_add(1);
+                bucket.matches_played = bucket.matches_played.saturating_add(1);
+            }
+            NamespaceStat::TokensStaked { amount } => {
+                self.tokens_staked = self.tokens_staked.saturating_add(*amount);
+                bucket.tokens_staked = bucket.tokens_staked.saturating_add(*amount);
+            }
+        }
+    }
+}
+
+pub const NAMESPACE_STATS_SIZE: usize = 8 + // key
+32 + // namespace
+1 + // bump
+8 * ARTIFACT_TYPE_COUNT + // joins by type
+8 * ARTIFACT_TYPE_COUNT + // leaves by type
+8 + // items activated
+8 + // crafts completed
+8 + // matches played
+8 + // tokens staked
+4 + // number of buckets
+STATS_BUCKET_SIZE * STATS_BUCKET_COUNT; // daily buckets
+
+/// Standing approval from a namespace to take in artifacts migrating from the source namespace.
+/// seed ['namespace', namespace program, namespace, 'migration', source namespace]
+#[account]
+pub struct NamespaceMigrationPermit {
+    pub namespace: Pubkey,
+    pub source_namespace: Pubkey,
+    pub bump: u8,
+}
+
+pub const MIGRATION_PERMIT_SIZE: usize = 8 + // key
+32 + // namespace
+32 + // source namespace
+1; // bump
+
+/// Only exists for artifacts that joined while the namespace had a membership duration.
+/// Artifacts that joined before one was set have no record and stay members for good.
+/// seed ['namespace', namespace program, namespace, artifact, 'membership']
+#[account]
+pub struct NamespaceMembership {
+    pub namespace: Pubkey,
+    pub artifact: Pubkey,
+    /// Gets the rent back when the membership is expired.
+    pub payer: Pubkey,
+    pub bump: u8,
+    pub expires_at: u64,
+}
+
+pub const MEMBERSHIP_SIZE: usize = 8 + // key
+32 + // namespace
+32 + // artifact
+32 + // payer
+1 + // bump
+8; // expires at
+
+/// expires_at of a record reused by an artifact that rejoined after the namespace
+/// stopped expiring memberships.
+pub const NEVER_EXPIRES: u64 = u64::MAX;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NamespaceInheritance {
+    pub parent: Pubkey,
+    pub permissiveness_settings: InheritanceState,
+    pub whitelisted_staking_mints: InheritanceState,
+    pub gatekeeper: InheritanceState,
+}
+
+pub const NAMESPACE_INHERITANCE_SIZE: usize = 32 + // parent
+1 + // permissiveness settings
+1 + // whitelisted staking mints
+1; // gatekeeper
+
+/// seed ['namespace', namespace program, namespace, wallet, 'quota']
+#[account]
+pub struct NamespaceWalletQuota {
+    pub namespace: Pubkey,
+    pub wallet: Pubkey,
+    pub bump: u8,
+    pub artifacts_joined: u64,
+}
+
+pub const WALLET_QUOTA_SIZE: usize = 8 + // key
+32 + // namespace
+32 + // wallet
+1 + // bump
+8; // artifacts joined
+
+pub const MAX_MULTISIG_SIGNERS: usize = 10;
+
+/// Selected by the extra_identifier (code) of an item usage or match token validation
+/// callback pointing at this program. Every check that is turned on must pass.
+/// seed ['namespace', namespace program, namespace, 'validator', code]
+#[account]
+pub struct NamespaceValidator {
+    pub namespace: Pubkey,
+    pub code: u64,
+    pub bump: u8,
+    /// Item or player must be a member of the namespace.
+    pub require_membership: bool,
+    /// Item usage must have been used fewer than this many times.
+    pub max_activations: Option<u64>,
+    /// Wallet must hold the mint of some other artifact that is a member of the namespace.
+    pub require_pass: bool,
+}
+
+pub const NAMESPACE_VALIDATOR_SIZE: usize = 8 + // key
+32 + // namespace
+8 + // code
+1 + // bump
+1 + // require membership
+9 + // max activations
+1 + // require pass
+50; // padding
+
+/// seed ['namespace', namespace program, namespace, 'multisig']
+#[account]
+pub struct NamespaceMultisig {
+    pub namespace: Pubkey,
+    pub bump: u8,
+    pub threshold: u8,
+    pub signers: Vec<Pubkey>,
+}
+
+pub const NAMESPACE_MULTISIG_SIZE: usize = 8 + // key
+32 + // namespace
+1 + // bump
+1 + // threshold
+4 + // number of signers
+32 * MAX_MULTISIG_SIGNERS; // signers
+
+/// seed ['namespace', namespace program, namespace, artifact type seed, page number]
 #[account]
 pub struct NamespaceIndex {
+    pub namespace: Pubkey,
+    pub bump: u8,
+    pub page: u64,
+    pub artifact_type: ArtifactType,
+    /// Paid for the page, and gets its rent back when it is closed.
+    pub payer: Pubkey,
+    pub caches: Vec<CachedArtifact>,
+}
+
+/// The layout of index pages from before they were split by artifact type. They share the
+/// NamespaceIndex discriminator, so they are read by hand until migrate_legacy_index_entry
+/// has drained them.
+/// seed ['namespace', namespace program, namespace, page number]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyNamespaceIndex {
     pub namespace: Pubkey,
     pub bump: u8,
     pub page: u64,
     pub caches: Vec<Pubkey>,
 }
 
+impl LegacyNamespaceIndex {
+    pub fn load(index: &AccountInfo) -> Result<LegacyNamespaceIndex> {
+        if index.owner != &crate::id() || index.data_is_empty() {
+            return Err(error!(ErrorCode::NotCachedHere));
+        }
+        let data = index.try_borrow_data()?;
+        if data[..8] != utils::get_account_discriminator("NamespaceIndex") {
+            return Err(error!(ErrorCode::NotCachedHere));
+        }
+        LegacyNamespaceIndex::deserialize(&mut &data[8..])
+            .map_err(|_| error!(ErrorCode::NotCachedHere))
+    }
+
+    pub fn save(&self, index: &AccountInfo) -> Result<()> {
+        let mut data = index.try_borrow_mut_data()?;
+        let mut writer = &mut data[8..];
+        self.serialize(&mut writer)?;
+        Ok(())
+    }
+}
+
+/// An index entry, tagged so clients can tell what it is without fetching it.
+/// The owning program follows from the artifact type.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CachedArtifact {
+    pub key: Pubkey,
+    pub artifact_type: ArtifactType,
+    /// Item classes and player classes, as opposed to items and players
+    pub is_class: bool,
+}
+
+pub const CACHED_ARTIFACT_SIZE: usize = 32 + // key
+1 + // artifact type
+1; // is class
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct NamespaceAndIndex {
     namespace: Pubkey,
@@ -522,6 +3294,21 @@ pub struct NamespaceAndIndex {
     inherited: InheritanceState,
 }
 
+impl NamespaceAndIndex {
+    pub fn new(namespace: Pubkey) -> Self {
+        NamespaceAndIndex {
+            namespace,
+            indexed: false,
+            inherited: InheritanceState::NotInherited,
+        }
+    }
+
+    /// Unused slots hold the system program.
+    pub fn empty() -> Self {
+        Self::new(anchor_lang::solana_program::system_program::id())
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
 pub enum InheritanceState {
     NotInherited,
@@ -529,6 +3316,16 @@ pub enum InheritanceState {
     Overridden,
 }
 
+impl InheritanceState {
+    /// A locally set value stops an inherited field from following its parent.
+    pub fn override_parent(&self) -> InheritanceState {
+        match self {
+            InheritanceState::Inherited => InheritanceState::Overridden,
+            other => other.clone(),
+        }
+    }
+}
+
 pub const NAMESPACE_AND_INDEX_SIZE: usize = 34;
 
 pub const MIN_NAMESPACE_SIZE: usize = 8 + // key
@@ -545,14 +3342,28 @@ pub const MIN_NAMESPACE_SIZE: usize = 8 + // key
 6 + // permissivenesses
 1 + // bump
 5 + // whitelist staking mints
+33 + // authority
+4 + // number of delegates
+NAMESPACE_DELEGATE_SIZE * MAX_DELEGATES + // delegates
+1 + NAMESPACE_FEE_CONFIG_SIZE + // fee config
+1 + NAMESPACE_LIMITS_SIZE + // limits
+8 * ARTIFACT_TYPE_COUNT + // artifacts added by type
+1 + NAMESPACE_INHERITANCE_SIZE + // inheritance
+8 * ARTIFACT_TYPE_COUNT + // highest page by type
+9 + // membership duration
+1 + // frozen
+33 + // activity log
+33 + // stats
 200; // padding
 
 pub const INDEX_SIZE: usize = 8 + // key
 32 + // namespace
 1 + // bump
 8 + // page
+1 + // artifact type
+32 + // payer
 4 + // amount in vec
-32 * MAX_CACHED_ITEMS + // array space
+CACHED_ARTIFACT_SIZE * MAX_CACHED_ITEMS + // array space
 100; //padding
 
 pub const ARTIFACT_FILTER_SIZE: usize = 8 + // key
@@ -600,12 +3411,12 @@ pub struct UpdateNamespace<'info> {
         bump=namespace.bump
     )]
     namespace: Account<'info, Namespace>,
-    #[account(
-        // Check to make sure the token owner is the signer, and that the token is an nft, decimals == 0
-        constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1
-    )]
     namespace_token: Account<'info, TokenAccount>,
     token_holder: Signer<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
@@ -613,7 +3424,6 @@ pub struct UpdateNamespace<'info> {
 pub struct CreateNamespaceGatekeeper<'info> {
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
     namespace: Account<'info, Namespace>,
-    #[account(constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1)]
     namespace_token: Account<'info, TokenAccount>,
     #[account(init, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), GATEKEEPER.as_bytes()], bump, payer=payer, space=space)]
     namespace_gatekeeper: Account<'info, NamespaceGatekeeper>,
@@ -628,7 +3438,34 @@ pub struct CreateNamespaceGatekeeper<'info> {
 pub struct AddToNamespaceGatekeeper<'info> {
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
     namespace: Account<'info, Namespace>,
-    #[account(constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1)]
+    namespace_token: Account<'info, TokenAccount>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), GATEKEEPER.as_bytes()], bump=namespace_gatekeeper.bump)]
+    namespace_gatekeeper: Account<'info, NamespaceGatekeeper>,
+    token_holder: Signer<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ReplaceInNamespaceGatekeeper<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), GATEKEEPER.as_bytes()], bump=namespace_gatekeeper.bump)]
+    namespace_gatekeeper: Account<'info, NamespaceGatekeeper>,
+    token_holder: Signer<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ReorderNamespaceGatekeeper<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
     namespace_token: Account<'info, TokenAccount>,
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), GATEKEEPER.as_bytes()], bump=namespace_gatekeeper.bump)]
     namespace_gatekeeper: Account<'info, NamespaceGatekeeper>,
@@ -639,7 +3476,6 @@ pub struct AddToNamespaceGatekeeper<'info> {
 pub struct RemoveFromNamespaceGatekeeper<'info> {
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
     namespace: Account<'info, Namespace>,
-    #[account(constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1)]
     namespace_token: Account<'info, TokenAccount>,
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), GATEKEEPER.as_bytes()], bump=namespace_gatekeeper.bump)]
     namespace_gatekeeper: Account<'info, NamespaceGatekeeper>,
@@ -700,6 +3536,32 @@ pub struct ItemValidation<'info> {
 pub struct MatchValidation<'info> {
     source_item_or_player_pda: UncheckedAccount<'info>,
     mint: UncheckedAccount<'info>,
+    source_token_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: NamespaceValidatorArgs)]
+pub struct CreateNamespaceValidator<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(init, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), VALIDATOR.as_bytes(), &args.code.to_le_bytes()], bump, payer=payer, space=NAMESPACE_VALIDATOR_SIZE)]
+    validator: Account<'info, NamespaceValidator>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNamespaceValidator<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), VALIDATOR.as_bytes(), &validator.code.to_le_bytes()], bump=validator.bump)]
+    validator: Account<'info, NamespaceValidator>,
 }
 
 #[derive(Accounts)]
@@ -707,13 +3569,35 @@ pub struct MatchValidation<'info> {
 pub struct JoinNamespace<'info> {
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
     namespace: Account<'info, Namespace>,
-    #[account(constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1)]
     namespace_token: Account<'info, TokenAccount>,
     #[account(mut)]
     artifact: UncheckedAccount<'info>,
-    #[account(seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), PREFIX.as_bytes()], bump=namespace_gatekeeper_bump)]
+    // Checked in the handler, may be the parent namespace's gatekeeper.
     namespace_gatekeeper: UncheckedAccount<'info>,
     token_holder: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), TREASURY.as_bytes()], bump)]
+    treasury: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    payer_token_account: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    treasury_token_account: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+    // Only used when the namespace caps joins per wallet, the token account holding the artifact.
+    artifact_token_account: UncheckedAccount<'info>,
+    // Only created and used when the namespace caps joins per wallet, seed
+    // ['namespace', namespace, artifact holder, 'quota']. Checked in the handler.
+    #[account(mut)]
+    wallet_quota: UncheckedAccount<'info>,
+    // Only created and used when the namespace has a membership duration.
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), artifact.key().as_ref(), MEMBERSHIP.as_bytes()], bump)]
+    membership: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+    clock: Sysvar<'info, Clock>,
 }
 
 #[derive(Accounts)]
@@ -721,13 +3605,19 @@ pub struct JoinNamespace<'info> {
 pub struct LeaveNamespace<'info> {
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
     namespace: Account<'info, Namespace>,
-    #[account(constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1)]
     namespace_token: Account<'info, TokenAccount>,
     #[account(mut)]
     artifact: UncheckedAccount<'info>,
-    #[account(seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), PREFIX.as_bytes()], bump=namespace_gatekeeper_bump)]
+    #[account(seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), GATEKEEPER.as_bytes()], bump=namespace_gatekeeper_bump)]
     namespace_gatekeeper: UncheckedAccount<'info>,
+    // Must sign as the namespace authority, see assert_namespace_authority.
     token_holder: UncheckedAccount<'info>,
+    // Closed if it exists.
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), artifact.key().as_ref(), MEMBERSHIP.as_bytes()], bump)]
+    membership: UncheckedAccount<'info>,
+    // Gets the rent of the membership, so it must be its payer. Unused without a membership.
+    #[account(mut)]
+    receiver: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
@@ -735,15 +3625,28 @@ pub struct LeaveNamespace<'info> {
 pub struct CacheArtifact<'info> {
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
     namespace: Account<'info, Namespace>,
-    #[account(constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1)]
     namespace_token: Account<'info, TokenAccount>,
-    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.page.to_string().as_bytes()], bump=index.bump)]
-    index: Account<'info, NamespaceIndex>,
-    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.page.checked_sub(1).ok_or(0).unwrap().to_string().as_bytes()], bump=prior_index.bump)]
-    prior_index: Account<'info, NamespaceIndex>,
+    // Created on the fly if this is a new page.
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), args.page.to_string().as_bytes()], bump)]
+    index: UncheckedAccount<'info>,
+    // Unused on page 0, pass the index again.
+    #[account(seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), args.page.saturating_sub(1).to_string().as_bytes()], bump)]
+    prior_index: UncheckedAccount<'info>,
     #[account(mut)]
     artifact: UncheckedAccount<'info>,
+    // Checked in the handler, may be the parent namespace's gatekeeper.
+    namespace_gatekeeper: UncheckedAccount<'info>,
     token_holder: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), TREASURY.as_bytes()], bump)]
+    treasury: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    payer_token_account: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    treasury_token_account: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+    #[account(mut)]
     payer: Signer<'info>,
     system_program: Program<'info, System>,
     rent: Sysvar<'info, Rent>,
@@ -753,17 +3656,324 @@ pub struct CacheArtifact<'info> {
 pub struct UncacheArtifact<'info> {
     #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
     namespace: Account<'info, Namespace>,
-    #[account(constraint=namespace_token.owner == token_holder.key() && namespace_token.amount == 1)]
     namespace_token: Account<'info, TokenAccount>,
-    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.page.to_string().as_bytes()], bump=index.bump)]
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), args.page.to_string().as_bytes()], bump=index.bump)]
     index: Account<'info, NamespaceIndex>,
     #[account(mut)]
     artifact: UncheckedAccount<'info>,
     token_holder: UncheckedAccount<'info>,
-    // Received of funds
+    // Receiver of funds from the highest page if it empties out, must be its payer
+    receiver: UncheckedAccount<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawNamespaceFees<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), TREASURY.as_bytes()], bump)]
+    treasury: UncheckedAccount<'info>,
+    // Only used when withdrawing SPL fees, otherwise pass the system program.
+    #[account(mut)]
+    treasury_token_account: UncheckedAccount<'info>,
+    // Receives SOL directly, or for SPL fees an existing token account of the fee mint.
+    #[account(mut)]
+    receiver: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: ExpireNamespaceMembershipArgs)]
+pub struct ExpireNamespaceMembership<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    #[account(mut)]
+    artifact: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), artifact.key().as_ref(), MEMBERSHIP.as_bytes()], bump=membership.bump, close=receiver)]
+    membership: Account<'info, NamespaceMembership>,
+    // Only used if the artifact is still cached, otherwise pass the membership again.
+    #[account(mut)]
+    index: UncheckedAccount<'info>,
+    #[account(mut, constraint=receiver.key() == membership.payer)]
+    receiver: UncheckedAccount<'info>,
+    clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: MigrateArtifactArgs)]
+pub struct MigrateArtifact<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), source_namespace_token.mint.as_ref()], bump=source_namespace.bump)]
+    source_namespace: Account<'info, Namespace>,
+    source_namespace_token: Account<'info, TokenAccount>,
+    source_token_holder: UncheckedAccount<'info>,
+    // The source authority if it is a multisig, otherwise pass the system program. Co-signers
+    // for either side go in remaining accounts.
+    source_multisig: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), destination_namespace_token.mint.as_ref()], bump=destination_namespace.bump)]
+    destination_namespace: Account<'info, Namespace>,
+    destination_namespace_token: Account<'info, TokenAccount>,
+    // Need not sign if the destination has a migration permit for the source.
+    destination_token_holder: UncheckedAccount<'info>,
+    // The destination authority if it is a multisig, otherwise pass the system program.
+    destination_multisig: UncheckedAccount<'info>,
+    #[account(mut)]
+    artifact: UncheckedAccount<'info>,
+    // Checked in the handler, may be the parent namespace's gatekeeper.
+    destination_gatekeeper: UncheckedAccount<'info>,
+    // Only used if the artifact is cached in the source, checked in the handler.
+    #[account(mut)]
+    source_index: UncheckedAccount<'info>,
+    // Only used if the artifact is cached in the source and this page exists.
+    #[account(mut, seeds=[PREFIX.as_bytes(), destination_namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), args.destination_page.to_string().as_bytes()], bump)]
+    destination_index: UncheckedAccount<'info>,
+    #[account(seeds=[PREFIX.as_bytes(), destination_namespace.key().as_ref(), MIGRATION.as_bytes(), source_namespace.key().as_ref()], bump)]
+    migration_permit: UncheckedAccount<'info>,
+    // Closed if it exists.
+    #[account(mut, seeds=[PREFIX.as_bytes(), source_namespace.key().as_ref(), artifact.key().as_ref(), MEMBERSHIP.as_bytes()], bump)]
+    source_membership: UncheckedAccount<'info>,
+    // Only created and used when the destination has a membership duration.
+    #[account(mut, seeds=[PREFIX.as_bytes(), destination_namespace.key().as_ref(), artifact.key().as_ref(), MEMBERSHIP.as_bytes()], bump)]
+    destination_membership: UncheckedAccount<'info>,
+    // Only used when the destination caps joins per wallet, the token account holding the artifact.
+    artifact_token_account: UncheckedAccount<'info>,
+    // Only created and used when the destination caps joins per wallet, seed
+    // ['namespace', destination, artifact holder, 'quota']. Checked in the handler.
+    #[account(mut)]
+    wallet_quota: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), destination_namespace.key().as_ref(), TREASURY.as_bytes()], bump)]
+    treasury: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    payer_token_account: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    treasury_token_account: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+    // Gets the rent of the source membership, so it must be its payer, and of the source
+    // index page if that empties out.
+    #[account(mut)]
+    receiver: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+    clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMigrationPermit<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    source_namespace: Account<'info, Namespace>,
+    #[account(init, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), MIGRATION.as_bytes(), source_namespace.key().as_ref()], bump, payer=payer, space=MIGRATION_PERMIT_SIZE)]
+    migration_permit: Account<'info, NamespaceMigrationPermit>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeMigrationPermit<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), MIGRATION.as_bytes(), migration_permit.source_namespace.as_ref()], bump=migration_permit.bump, close=receiver)]
+    migration_permit: Account<'info, NamespaceMigrationPermit>,
+    #[account(mut)]
+    receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RenewMembership<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    artifact: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), artifact.key().as_ref(), MEMBERSHIP.as_bytes()], bump=membership.bump)]
+    membership: Account<'info, NamespaceMembership>,
+    // Checked in the handler, may be the parent namespace's gatekeeper.
+    namespace_gatekeeper: UncheckedAccount<'info>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), TREASURY.as_bytes()], bump)]
+    treasury: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    payer_token_account: UncheckedAccount<'info>,
+    // Only used when fees are in an SPL mint, otherwise pass the system program.
+    #[account(mut)]
+    treasury_token_account: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: CompactNamespaceIndexArgs)]
+pub struct CompactNamespaceIndex<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), args.page.to_string().as_bytes()], bump=index.bump)]
+    index: Account<'info, NamespaceIndex>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), namespace.highest_page_by_type[args.artifact_type.get_index()].to_string().as_bytes()], bump=highest_index.bump)]
+    highest_index: Account<'info, NamespaceIndex>,
+    // Receiver of funds from the highest page if it empties out, must be its payer
+    #[account(mut)]
+    receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: MigrateLegacyIndexEntryArgs)]
+pub struct MigrateLegacyIndexEntry<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    // Only the highest of the old pages can be drained.
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), namespace.highest_page.to_string().as_bytes()], bump)]
+    legacy_index: UncheckedAccount<'info>,
+    // Created on the fly if this is a new page.
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), args.page.to_string().as_bytes()], bump)]
+    index: UncheckedAccount<'info>,
+    // Unused on page 0, pass the index again.
+    #[account(seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), args.artifact_type.get_seed().as_bytes(), args.page.saturating_sub(1).to_string().as_bytes()], bump)]
+    prior_index: UncheckedAccount<'info>,
+    artifact: UncheckedAccount<'info>,
+    // Receiver of funds from the old page once it empties out
+    #[account(mut)]
     receiver: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateNamespaceMultisig<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    #[account(init, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), MULTISIG.as_bytes()], bump, payer=payer, space=NAMESPACE_MULTISIG_SIZE)]
+    namespace_multisig: Account<'info, NamespaceMultisig>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNamespaceMultisig<'info> {
+    #[account(seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), MULTISIG.as_bytes()], bump=namespace_multisig.bump)]
+    namespace_multisig: Account<'info, NamespaceMultisig>,
+    token_holder: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetNamespaceAuthority<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateNamespaceActivityLog<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(init, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), ACTIVITY.as_bytes()], bump, payer=payer, space=ACTIVITY_LOG_SIZE)]
+    activity_log: Account<'info, NamespaceActivityLog>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateNamespaceStats<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(init, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), STATS.as_bytes()], bump, payer=payer, space=NAMESPACE_STATS_SIZE)]
+    namespace_stats: Account<'info, NamespaceStats>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RecordNamespaceStat<'info> {
+    namespace: Account<'info, Namespace>,
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.key().as_ref(), STATS.as_bytes()], bump=namespace_stats.bump)]
+    namespace_stats: Account<'info, NamespaceStats>,
+    artifact: UncheckedAccount<'info>,
+    stats_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetNamespaceParent<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace_token.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    namespace_token: Account<'info, TokenAccount>,
+    token_holder: UncheckedAccount<'info>,
+    #[account(seeds=[PREFIX.as_bytes(), parent.mint.as_ref()], bump=parent.bump)]
+    parent: Account<'info, Namespace>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNamespaceInheritance<'info> {
+    #[account(mut, seeds=[PREFIX.as_bytes(), namespace.mint.as_ref()], bump=namespace.bump)]
+    namespace: Account<'info, Namespace>,
+    #[account(seeds=[PREFIX.as_bytes(), parent.mint.as_ref()], bump=parent.bump)]
+    parent: Account<'info, Namespace>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ResizeNamespace<'info> {
+    #[account(mut)]
+    namespace: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
+    system_program: Program<'info, System>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct GrowNamespaceArray<'info> {
+    #[account(mut)]
+    artifact: UncheckedAccount<'info>,
+    #[account(mut)]
+    payer: Signer<'info>,
     system_program: Program<'info, System>,
     rent: Sysvar<'info, Rent>,
+    // Unless the artifact is a namespace, the program that owns it [readable]
+    // in remaining accounts.
 }
 
 #[error_code]
@@ -816,4 +4026,100 @@ pub enum ErrorCode {
     CannotJoinNamespace,
     #[msg("You cannot remove an artifact from a namespace while it is still cached there. Uncache it first.")]
     ArtifactStillCached,
+    #[msg("Can only resize valid raindrops artifacts (players, items, matches, namespaces)")]
+    CanOnlyResizeValidRaindropsObjects,
+    #[msg("Must add at least one namespace slot")]
+    NamespaceArrayGrowthTooSmall,
+    #[msg("Cannot grow an account by more than 10KB in a single instruction")]
+    NamespaceArrayGrowthTooLarge,
+    #[msg("Signer is not the namespace authority and holds no delegated power for this action")]
+    NotNamespaceAuthority,
+    #[msg("Multisig threshold must be between 1 and the number of signers")]
+    InvalidMultisigThreshold,
+    #[msg("Too many multisig signers, 10 max")]
+    TooManyMultisigSigners,
+    #[msg("Multisig signers must be unique")]
+    DuplicateMultisigSigner,
+    #[msg("Too many delegates, 5 max")]
+    TooManyDelegates,
+    #[msg("Can only compact the highest page into a lower page")]
+    CanOnlyCompactIntoLowerPage,
+    #[msg("Artifacts must be passed in the order they come off the highest page")]
+    CompactionArtifactMismatch,
+    #[msg(
+        "Treasury token account must be the treasury's associated token account for the fee mint"
+    )]
+    InvalidTreasuryTokenAccount,
+    #[msg("Payer token account must hold the fee mint")]
+    InvalidFeePayerTokenAccount,
+    #[msg("This namespace has reached its artifact limit")]
+    NamespaceArtifactLimitReached,
+    #[msg("This namespace has reached its limit for this artifact type")]
+    ArtifactTypeLimitReached,
+    #[msg("This wallet has reached its join limit for this namespace")]
+    WalletJoinLimitReached,
+    #[msg("This namespace has reached its cache limit")]
+    NamespaceCacheLimitReached,
+    #[msg("Too many artifact type limits, one per artifact type max")]
+    TooManyArtifactTypeLimits,
+    #[msg("Namespace must join the parent namespace before inheriting from it")]
+    NotParentNamespace,
+    #[msg("Validator code 0 is reserved for no validation")]
+    ReservedValidatorCode,
+    #[msg("The namespace validator for this code must be passed in remaining accounts")]
+    NamespaceValidatorMissing,
+    #[msg("Activation limits only apply to item usages")]
+    ActivationLimitOnlyAppliesToItems,
+    #[msg("This item usage has reached its activation limit")]
+    ActivationLimitReached,
+    #[msg("Wallet does not hold a pass issued by this namespace")]
+    NamespacePassNotHeld,
+    #[msg("Account is not an item")]
+    NotAnItem,
+    #[msg("Source token account does not hold the mint being validated")]
+    SourceTokenAccountMintMismatch,
+    #[msg("Artifact type does not match the artifact")]
+    ArtifactTypeMismatch,
+    #[msg("Membership has not expired yet")]
+    MembershipNotExpired,
+    #[msg("This namespace has no membership duration, memberships do not expire")]
+    MembershipDoesNotExpire,
+    #[msg("Namespace is frozen")]
+    NamespaceFrozen,
+    #[msg("Every namespace the artifact belongs to must be passed in to check it is not frozen")]
+    NamespaceAccountMissing,
+    #[msg("This namespace keeps an activity log, it must be passed in remaining accounts")]
+    ActivityLogMissing,
+    #[msg("This account is not a raindrops artifact")]
+    NotARaindropsArtifact,
+    #[msg("The artifact's namespaces array does not fit in the account")]
+    MalformedNamespaceArray,
+    #[msg("Namespace slot is out of range")]
+    NamespaceSlotOutOfRange,
+    #[msg("There is no gatekeeper filter at that index")]
+    GatekeeperFilterIndexOutOfRange,
+    #[msg(
+        "The destination namespace authority must sign, or leave a migration permit for the source"
+    )]
+    MigrationNotApproved,
+    #[msg("Artifact is already in this namespace")]
+    ArtifactAlreadyInNamespace,
+    #[msg("The receiver must be whoever paid for the membership")]
+    ReceiverMustBeMembershipPayer,
+    #[msg("This namespace keeps stats, they must be passed in remaining accounts")]
+    NamespaceStatsMissing,
+    #[msg("This stat cannot be recorded by the signing program")]
+    StatNotRecordableByProgram,
+    #[msg("The artifact's data is too short or malformed")]
+    MalformedArtifact,
+    #[msg("The program that owns the artifact must be passed in remaining accounts")]
+    ArtifactProgramMissing,
+    #[msg("The artifact token account must hold the artifact's mint")]
+    ArtifactNotHeld,
+    #[msg("The receiver must be whoever paid for the index page")]
+    ReceiverMustBeIndexPayer,
+    #[msg("The receiver must be a token account of the fee mint")]
+    InvalidFeeReceiverTokenAccount,
+    #[msg("The treasury must keep enough SOL to stay rent exempt")]
+    TreasuryBelowRent,
 }
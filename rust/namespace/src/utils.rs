@@ -1,23 +1,29 @@
 use {
     crate::{
-        ErrorCode, Filter, Namespace, NamespaceAndIndex, NamespaceGatekeeper, Permissiveness,
-        NAMESPACE_AND_INDEX_SIZE,
+        artifact::{is_raindrops_artifact, ArtifactNamespaces},
+        ArtifactType, ErrorCode, Filter, Namespace, NamespaceAdminPower, NamespaceAndIndex,
+        NamespaceGatekeeper, NamespaceMultisig, NamespaceStat, Permissiveness,
+        MAX_MULTISIG_SIGNERS, NAMESPACE_AND_INDEX_SIZE, STATS_AUTHORITY,
     },
     anchor_lang::{
         error,
         prelude::{
-            msg, Account, AccountInfo, ProgramError, Pubkey, Rent, Result, SolanaSysvar,
-            UncheckedAccount,
+            msg, Account, AccountInfo, Program, ProgramError, Pubkey, Rent, Result, Signer,
+            SolanaSysvar, System, UncheckedAccount,
         },
         solana_program::{
-            borsh::try_from_slice_unchecked,
+            entrypoint::MAX_PERMITTED_DATA_INCREASE,
+            hash::hash,
+            instruction::Instruction,
             program::{invoke, invoke_signed},
             program_pack::{IsInitialized, Pack},
             system_instruction,
         },
-        Key, ToAccountInfo,
+        InstructionData, Key, ToAccountInfo, ToAccountMetas,
     },
+    anchor_spl::token::{Token, TokenAccount},
     arrayref::array_ref,
+    spl_associated_token_account::get_associated_token_address,
     std::{convert::TryInto, str::FromStr},
 };
 
@@ -156,6 +162,127 @@ pub fn create_or_allocate_account_raw<'a>(
     Ok(())
 }
 
+pub struct ReallocWithRentParams<'a, 'b> {
+    pub account: &'b AccountInfo<'a>,
+    pub payer: &'b AccountInfo<'a>,
+    pub system_program: &'b AccountInfo<'a>,
+    pub rent: &'b AccountInfo<'a>,
+    pub new_len: usize,
+}
+
+/// Grows an account we own to new_len, with the payer topping up rent.
+pub fn realloc_with_rent(params: ReallocWithRentParams<'_, '_>) -> Result<()> {
+    let ReallocWithRentParams {
+        account,
+        payer,
+        system_program,
+        rent,
+        new_len,
+    } = params;
+
+    if new_len.saturating_sub(account.data_len()) > MAX_PERMITTED_DATA_INCREASE {
+        return Err(error!(ErrorCode::NamespaceArrayGrowthTooLarge));
+    }
+
+    let rent = &Rent::from_account_info(rent)?;
+    let required_lamports = rent
+        .minimum_balance(new_len)
+        .saturating_sub(account.lamports());
+
+    if required_lamports > 0 {
+        msg!("Transfer {} lamports to the account", required_lamports);
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, required_lamports),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    account.realloc(new_len, false)?;
+    Ok(())
+}
+
+pub struct GrowNamespaceArrayParams<'a, 'b> {
+    pub artifact: &'b AccountInfo<'a>,
+    pub payer: &'b AccountInfo<'a>,
+    pub system_program: &'b AccountInfo<'a>,
+    pub rent: &'b AccountInfo<'a>,
+    pub additional_slots: usize,
+}
+
+/// Reallocs an artifact so its namespaces array has `additional_slots` more empty slots,
+/// shifting everything after the array down. The payer covers any extra rent.
+///
+/// Only the program that owns the artifact may realloc it, so each program with artifacts
+/// calls this from its own grow_namespace_array instruction.
+pub fn grow_namespace_array(params: GrowNamespaceArrayParams<'_, '_>) -> Result<()> {
+    let GrowNamespaceArrayParams {
+        artifact,
+        payer,
+        system_program,
+        rent,
+        additional_slots,
+    } = params;
+
+    if additional_slots == 0 {
+        return Err(error!(ErrorCode::NamespaceArrayGrowthTooSmall));
+    }
+
+    let old_len = artifact.data_len();
+    let namespaces = ArtifactNamespaces::load(artifact)
+        .map_err(|_| error!(ErrorCode::CanOnlyResizeValidRaindropsObjects))?;
+
+    let (insert_at, existing_slots, added_bytes) = if namespaces.is_allocated() {
+        (
+            namespaces.end_offset(),
+            namespaces.len(),
+            additional_slots * NAMESPACE_AND_INDEX_SIZE,
+        )
+    } else {
+        // Going from None to Some also needs room for the vec length.
+        (
+            namespaces.end_offset(),
+            0,
+            4 + additional_slots * NAMESPACE_AND_INDEX_SIZE,
+        )
+    };
+
+    if added_bytes > MAX_PERMITTED_DATA_INCREASE {
+        return Err(error!(ErrorCode::NamespaceArrayGrowthTooLarge));
+    }
+
+    let new_len = old_len
+        .checked_add(added_bytes)
+        .ok_or(ErrorCode::NumericalOverflowError)?;
+    let new_slots: u32 = existing_slots
+        .checked_add(additional_slots)
+        .ok_or(ErrorCode::NumericalOverflowError)?
+        .try_into()
+        .map_err(|_| error!(ErrorCode::NumericalOverflowError))?;
+
+    realloc_with_rent(ReallocWithRentParams {
+        account: artifact,
+        payer,
+        system_program,
+        rent,
+        new_len,
+    })?;
+
+    {
+        let mut data = artifact.try_borrow_mut_data()?;
+        data.copy_within(insert_at..old_len, insert_at + added_bytes);
+
+        data[8] = 1; // Option yes.
+        data[9..13].copy_from_slice(&new_slots.to_le_bytes());
+    }
+
+    let namespaces = ArtifactNamespaces::load(artifact)?;
+    for slot in existing_slots..namespaces.len() {
+        namespaces.set(slot, &NamespaceAndIndex::empty())?;
+    }
+
+    Ok(())
+}
+
 pub fn spl_token_mint_to<'a: 'b, 'b>(
     mint: AccountInfo<'a>,
     destination: AccountInfo<'a>,
@@ -235,209 +362,796 @@ pub fn assert_part_of_namespace<'a>(
     artifact: &AccountInfo<'a>,
     namespace: &Account<'a, Namespace>,
 ) -> Result<()> {
-    let data = artifact.data.borrow_mut();
-    let number = u32::from_le_bytes(*array_ref![data, 8, 4]) as usize;
-    let offset = 12 as usize;
-    for i in 0..number {
-        let key_bytes = array_ref![data, offset + i * 33, 32];
-        let key = Pubkey::new_from_array(*key_bytes);
-        if key == namespace.key() {
-            return Ok(());
+    if !ArtifactNamespaces::load(artifact)?.contains(&namespace.key())? {
+        return Err(error!(ErrorCode::ArtifactLacksNamespace));
+    }
+    Ok(())
+}
+
+/// Like assert_part_of_namespace, but safe to call on accounts that may not be raindrops artifacts.
+pub fn is_part_of_namespace(artifact: &AccountInfo, namespace: &Pubkey) -> bool {
+    ArtifactNamespaces::load(artifact)
+        .and_then(|namespaces| namespaces.contains(namespace))
+        .unwrap_or(false)
+}
+
+/// Fails if the artifact belongs to any frozen namespace. Every namespace the artifact is in
+/// has to be among the given accounts, in any order, so other programs can pass them in
+/// after whatever remaining accounts they already use.
+pub fn assert_not_frozen_in_namespaces<'a>(
+    artifact: &AccountInfo<'a>,
+    namespace_accounts: &[AccountInfo<'a>],
+) -> Result<()> {
+    if artifact.data_is_empty() {
+        return Ok(());
+    }
+    if let Some(art_namespaces) = pull_namespaces(artifact)? {
+        for n in art_namespaces {
+            if n.namespace == anchor_lang::solana_program::system_program::id() {
+                continue;
+            }
+            let namespace_info = namespace_accounts
+                .iter()
+                .find(|acct| acct.key == &n.namespace)
+                .ok_or(ErrorCode::NamespaceAccountMissing)?;
+            let namespace: Account<Namespace> = Account::try_from(namespace_info)?;
+            if namespace.frozen {
+                return Err(error!(ErrorCode::NamespaceFrozen));
+            }
         }
     }
+    Ok(())
+}
+
+pub struct RecordStatInNamespacesArgs<'a, 'info> {
+    pub artifact: &'a AccountInfo<'info>,
+    pub stat: NamespaceStat,
+    /// The calling program, whose ['stats_authority'] PDA signs for the stat.
+    pub program_id: &'a Pubkey,
+    pub remaining_accounts: &'a [AccountInfo<'info>],
+}
+
+/// For the other raindrops programs. Records the stat on every namespace the artifact is in
+/// that was passed in remaining accounts and keeps stats, as long as the stats account, the
+/// caller's stats authority and the namespace program were passed too. Stats are best effort,
+/// a namespace missing any of them is skipped rather than failing the instruction.
+pub fn record_stat_in_namespaces(args: RecordStatInNamespacesArgs) -> Result<()> {
+    let RecordStatInNamespacesArgs {
+        artifact,
+        stat,
+        program_id,
+        remaining_accounts,
+    } = args;
+
+    if artifact.data_is_empty() {
+        return Ok(());
+    }
+    let find = |key: &Pubkey| remaining_accounts.iter().find(|acct| acct.key == key);
 
-    return Err(error!(ErrorCode::ArtifactLacksNamespace));
+    for n in pull_namespaces(artifact)?.unwrap_or_default() {
+        if n.namespace == anchor_lang::solana_program::system_program::id() {
+            continue;
+        }
+        let namespace_info = match find(&n.namespace) {
+            Some(namespace_info) => namespace_info,
+            None => continue,
+        };
+        let namespace: Account<Namespace> = Account::try_from(namespace_info)?;
+        let stats_key = match namespace.stats {
+            Some(stats_key) => stats_key,
+            None => continue,
+        };
+
+        let (stats_authority, bump) =
+            Pubkey::find_program_address(&[STATS_AUTHORITY.as_bytes()], program_id);
+        let (stats_info, stats_authority_info, namespace_program_info) =
+            match (find(&stats_key), find(&stats_authority), find(&crate::id())) {
+                (Some(stats), Some(authority), Some(program)) => (stats, authority, program),
+                _ => continue,
+            };
+        invoke_signed(
+            &Instruction {
+                program_id: crate::id(),
+                accounts: crate::accounts::RecordNamespaceStat {
+                    namespace: n.namespace,
+                    namespace_stats: stats_key,
+                    artifact: artifact.key(),
+                    stats_authority,
+                }
+                .to_account_metas(None),
+                data: crate::instruction::RecordNamespaceStat { stat: stat.clone() }.data(),
+            },
+            &[
+                namespace_info.clone(),
+                stats_info.clone(),
+                artifact.clone(),
+                stats_authority_info.clone(),
+                namespace_program_info.clone(),
+            ],
+            &[&[STATS_AUTHORITY.as_bytes(), &[bump]]],
+        )?;
+    }
+    Ok(())
 }
 
+/// Flips the artifact's indexed flag for this namespace, returning the old value.
 pub fn inverse_indexed_bool_for_namespace(
     artifact: &mut UncheckedAccount,
     namespace: Pubkey,
 ) -> Result<u8> {
-    let mut data = artifact.data.borrow_mut();
-    let mut start: usize = 12;
-    let found = false;
-    while found == false && start < data.len() {
-        let bytes = array_ref![data, start, 32];
-        let key = Pubkey::new_from_array(*bytes);
-        if key == namespace {
-            let old_val = data[start + 33];
-            data[start + 33] = if data[start + 33] == 1 { 0 } else { 1 };
-            return Ok(old_val);
+    let namespaces = ArtifactNamespaces::load(artifact)?;
+    let slot = namespaces
+        .position(&namespace)?
+        .ok_or(ErrorCode::ArtifactNotPartOfNamespace)?;
+    let old_val = namespaces.get(slot)?.indexed;
+    namespaces.set_indexed(&namespace, !old_val)?;
+    Ok(old_val as u8)
+}
+
+pub fn set_indexed_bool_for_namespace(
+    artifact: &AccountInfo,
+    namespace: Pubkey,
+    indexed: bool,
+) -> Result<()> {
+    ArtifactNamespaces::load(artifact)?.set_indexed(&namespace, indexed)?;
+    Ok(())
+}
+
+/// Empties out an index page, sending its rent to the receiver.
+pub fn close_index_page(index: &AccountInfo, receiver: &AccountInfo) -> Result<()> {
+    let curr_lamp = index.lamports();
+    **index.lamports.borrow_mut() = 0;
+
+    **receiver.lamports.borrow_mut() = receiver
+        .lamports()
+        .checked_add(curr_lamp)
+        .ok_or(ErrorCode::NumericalOverflowError)?;
+    Ok(())
+}
+
+pub fn pull_namespaces(artifact: &AccountInfo) -> Result<Option<Vec<NamespaceAndIndex>>> {
+    ArtifactNamespaces::load(artifact)?.to_vec()
+}
+
+/// Anchor discriminator for an `#[account]` struct named `name`, so we can
+/// tell apart artifacts owned by programs this crate cannot depend on.
+pub fn get_account_discriminator(name: &str) -> [u8; 8] {
+    let preimage = format!("account:{}", name);
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(preimage.as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+fn read_artifact_bytes(data: &[u8], cursor: usize, len: usize) -> Result<&[u8]> {
+    cursor
+        .checked_add(len)
+        .and_then(|end| data.get(cursor..end))
+        .ok_or_else(|| error!(ErrorCode::MalformedArtifact))
+}
+
+fn read_artifact_byte(data: &[u8], cursor: usize) -> Result<u8> {
+    Ok(read_artifact_bytes(data, cursor, 1)?[0])
+}
+
+fn read_artifact_u32(data: &[u8], cursor: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        read_artifact_bytes(data, cursor, 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_pubkey(data: &[u8], cursor: usize) -> Result<Pubkey> {
+    Ok(Pubkey::new_from_array(
+        read_artifact_bytes(data, cursor, 32)?.try_into().unwrap(),
+    ))
+}
+
+fn read_option_pubkey(data: &[u8], cursor: &mut usize) -> Result<Option<Pubkey>> {
+    if read_artifact_byte(data, *cursor)? == 0 {
+        *cursor += 1;
+        Ok(None)
+    } else {
+        let key = read_pubkey(data, *cursor + 1)?;
+        *cursor += 33;
+        Ok(Some(key))
+    }
+}
+
+/// Reads the mint off of an artifact without deserializing it.
+///
+/// Namespace: [namespaces][mint]
+/// ItemClass, PlayerClass: [namespaces][parent: Option<Pubkey>][mint: Option<Pubkey>]
+/// Item: [namespaces][layout_version: u8][parent: Pubkey][class_index: u64][mint: Option<Pubkey>]
+/// Player: [namespaces][padding: u8][parent: Pubkey][mint: Option<Pubkey>]
+///
+/// Matches have no mint.
+pub fn read_artifact_mint(artifact: &AccountInfo) -> Result<Option<Pubkey>> {
+    if !is_raindrops_artifact(artifact) {
+        return Ok(None);
+    }
+    let mut cursor = ArtifactNamespaces::load(artifact)?.end_offset();
+    let data = artifact.data.borrow();
+    let discriminator = array_ref![data, 0, 8];
+
+    if artifact.owner == &crate::id() && *discriminator == get_account_discriminator("Namespace") {
+        return Ok(Some(read_pubkey(&data, cursor)?));
+    } else if artifact.owner == &Pubkey::from_str(crate::ITEM_ID).unwrap() {
+        if *discriminator == get_account_discriminator("ItemClass") {
+            read_option_pubkey(&data, &mut cursor)?;
+            return read_option_pubkey(&data, &mut cursor);
+        } else if *discriminator == get_account_discriminator("Item") {
+            cursor += 1 + 32 + 8;
+            return read_option_pubkey(&data, &mut cursor);
+        }
+    } else if artifact.owner == &Pubkey::from_str(crate::PLAYER_ID).unwrap() {
+        if *discriminator == get_account_discriminator("PlayerClass") {
+            read_option_pubkey(&data, &mut cursor)?;
+            return read_option_pubkey(&data, &mut cursor);
+        } else if *discriminator == get_account_discriminator("Player") {
+            cursor += 1 + 32;
+            return read_option_pubkey(&data, &mut cursor);
         }
-        start += 33;
     }
-    return Err(error!(ErrorCode::ArtifactNotPartOfNamespace));
+
+    Ok(None)
 }
 
-pub fn pull_namespaces(artifact: &AccountInfo) -> Result<Option<Vec<NamespaceAndIndex>>> {
-    let data = artifact.data.borrow_mut();
+fn read_option_string(data: &[u8], cursor: &mut usize) -> Result<Option<String>> {
+    if read_artifact_byte(data, *cursor)? == 0 {
+        *cursor += 1;
+        Ok(None)
+    } else {
+        let len = read_artifact_u32(data, *cursor + 1)? as usize;
+        let start = *cursor + 5;
+        let string = String::from_utf8_lossy(read_artifact_bytes(data, start, len)?).to_string();
+        *cursor = start + len;
+        Ok(Some(string))
+    }
+}
+
+/// Skips an Option of a { String, InheritanceState } struct, ie StatsUri.
+fn skip_option_inherited_string(data: &[u8], cursor: &mut usize) -> Result<()> {
+    if read_option_string(data, cursor)?.is_some() {
+        *cursor += 1;
+    }
+    Ok(())
+}
 
-    if data[8] == 0 {
+/// Reads the category off of an artifact without deserializing it. Categories are
+/// all stored as Option<{ category: String, inherited: InheritanceState }>.
+///
+/// ItemClass: [namespaces][parent: Option<Pubkey>][mint: Option<Pubkey>][metadata: Option<Pubkey>]
+///            [edition: Option<Pubkey>][bump: u8][existing_children: u64][layout_version: u8]
+///            [category]
+///            Legacy item classes have no layout version (their data starts with a 0 or 1
+///            Option tag) and no category.
+/// PlayerClass: [namespaces][parent: Option<Pubkey>][mint: Option<Pubkey>][metadata: Option<Pubkey>]
+///              [edition: Option<Pubkey>][starting_stats_uri: Option<StatsUri>][default_category]
+/// Player: [namespaces][padding: u8][parent: Pubkey][mint: Option<Pubkey>][metadata: Option<Pubkey>]
+///         [edition: Option<Pubkey>][stats_uri: Option<StatsUri>][category]
+///
+/// Items, matches and namespaces have no category.
+pub fn read_artifact_category(artifact: &AccountInfo) -> Result<Option<String>> {
+    if !is_raindrops_artifact(artifact) {
         return Ok(None);
     }
+    let mut cursor = ArtifactNamespaces::load(artifact)?.end_offset();
+    let data = artifact.data.borrow();
+    let discriminator = array_ref![data, 0, 8];
+
+    if artifact.owner == &Pubkey::from_str(crate::ITEM_ID).unwrap()
+        && *discriminator == get_account_discriminator("ItemClass")
+    {
+        for _ in 0..4 {
+            read_option_pubkey(&data, &mut cursor)?;
+        }
+        cursor += 1 + 8;
+        if read_artifact_byte(&data, cursor)? <= 1 {
+            return Ok(None);
+        }
+        cursor += 1;
+        return read_option_string(&data, &mut cursor);
+    } else if artifact.owner == &Pubkey::from_str(crate::PLAYER_ID).unwrap() {
+        if *discriminator == get_account_discriminator("PlayerClass") {
+            for _ in 0..4 {
+                read_option_pubkey(&data, &mut cursor)?;
+            }
+            skip_option_inherited_string(&data, &mut cursor)?;
+            return read_option_string(&data, &mut cursor);
+        } else if *discriminator == get_account_discriminator("Player") {
+            cursor += 1 + 32;
+            for _ in 0..3 {
+                read_option_pubkey(&data, &mut cursor)?;
+            }
+            skip_option_inherited_string(&data, &mut cursor)?;
+            return read_option_string(&data, &mut cursor);
+        }
+    }
 
-    let amount = u32::from_le_bytes(*array_ref![data, 9, 4]);
+    Ok(None)
+}
 
-    let cursor: usize = 13;
+/// Item classes and player classes, as opposed to their instances.
+pub fn is_artifact_class(artifact: &AccountInfo) -> bool {
+    let data = artifact.data.borrow();
+    if data.len() < 8 {
+        return false;
+    }
+    let discriminator = array_ref![data, 0, 8];
+    (artifact.owner == &Pubkey::from_str(crate::ITEM_ID).unwrap()
+        && *discriminator == get_account_discriminator("ItemClass"))
+        || (artifact.owner == &Pubkey::from_str(crate::PLAYER_ID).unwrap()
+            && *discriminator == get_account_discriminator("PlayerClass"))
+}
 
-    let mut arr: Vec<NamespaceAndIndex> = vec![];
-    for _n in 0..amount {
-        let bytes = array_ref![data, cursor, NAMESPACE_AND_INDEX_SIZE];
-        let serialized: NamespaceAndIndex = try_from_slice_unchecked(bytes)?;
-        arr.push(serialized)
+/// Reads how many times an item's usage has been used without deserializing it.
+///
+/// Item: [namespaces][layout_version: u8][parent: Pubkey][class_index: u64][mint: Option<Pubkey>]
+///       [metadata: Option<Pubkey>][edition: Option<Pubkey>][bump: u8][tokens_staked: u64]
+///       [usage_state_root: Option<Root>][usage_states: Option<Vec<ItemUsageState>>]
+/// ItemUsageState: [index: u16][uses: u64][activated_at: Option<u64>]
+///
+/// Returns None if the item has no state for this usage yet.
+pub fn read_item_usage_uses(item: &AccountInfo, usage_index: u16) -> Result<Option<u64>> {
+    if item.owner != &Pubkey::from_str(crate::ITEM_ID).unwrap()
+        || !is_raindrops_artifact(item)
+        || item.data.borrow()[..8] != get_account_discriminator("Item")
+    {
+        return Err(error!(ErrorCode::NotAnItem));
+    }
+    let mut cursor = ArtifactNamespaces::load(item)?.end_offset() + 1 + 32 + 8;
+    let data = item.data.borrow();
+    for _ in 0..3 {
+        read_option_pubkey(&data, &mut cursor)?;
+    }
+    cursor += 1 + 8;
+    if read_artifact_byte(&data, cursor)? == 1 {
+        // Root is an InheritanceState and a 32 byte hash.
+        cursor += 1 + 33;
+    } else {
+        cursor += 1;
+    }
+    if read_artifact_byte(&data, cursor)? == 0 {
+        return Ok(None);
+    }
+    let amount = read_artifact_u32(&data, cursor + 1)?;
+    cursor += 5;
+    for _ in 0..amount {
+        let state = read_artifact_bytes(&data, cursor, 2 + 8)?;
+        let index = u16::from_le_bytes(*array_ref![state, 0, 2]);
+        let uses = u64::from_le_bytes(*array_ref![state, 2, 8]);
+        if index == usage_index {
+            return Ok(Some(uses));
+        }
+        cursor += 2 + 8;
+        cursor += if read_artifact_byte(&data, cursor)? == 1 {
+            9
+        } else {
+            1
+        };
     }
 
-    return Ok(Some(arr));
+    Ok(None)
 }
 
+/// Figures out which kind of artifact we are looking at from the program that owns it.
+/// Matches are the only mission-like artifacts raindrops has, so they count as missions.
+pub fn get_artifact_type(artifact: &AccountInfo) -> Result<ArtifactType> {
+    if artifact.owner == &Pubkey::from_str(crate::PLAYER_ID).unwrap() {
+        Ok(ArtifactType::Player)
+    } else if artifact.owner == &Pubkey::from_str(crate::ITEM_ID).unwrap() {
+        Ok(ArtifactType::Item)
+    } else if artifact.owner == &Pubkey::from_str(crate::MATCH_ID).unwrap() {
+        Ok(ArtifactType::Mission)
+    } else if artifact.owner == &crate::id() {
+        Ok(ArtifactType::Namespace)
+    } else {
+        Err(error!(ErrorCode::CannotJoinNamespace))
+    }
+}
+
+/// Whatever the caller brought along to satisfy filters that need more than the artifact.
+pub struct FilterEvidence<'b, 'a> {
+    /// Proof of the artifact's mint for MerkleMint filters.
+    pub mint_proof: Option<&'b [[u8; 32]]>,
+    /// Searched for the artifact's metadata for Collection filters.
+    pub remaining_accounts: &'b [AccountInfo<'a>],
+}
+
+/// Finds the verified collection in a metaplex metadata account. The metadata crate we use
+/// predates collections, so we walk the borsh layout ourselves:
+///
+/// [key: u8][update_authority: Pubkey][mint: Pubkey][name: String][symbol: String][uri: String]
+/// [seller_fee_basis_points: u16][creators: Option<Vec<Creator>>][primary_sale_happened: bool]
+/// [is_mutable: bool][edition_nonce: Option<u8>][token_standard: Option<u8>]
+/// [collection: Option<{ verified: bool, key: Pubkey }>]
+///
+/// Returns None for metadata without a collection, or with one that is not verified.
+pub fn read_verified_collection(metadata: &AccountInfo) -> Option<Pubkey> {
+    let data = metadata.data.borrow();
+    let mut cursor: usize = 1 + 32 + 32;
+    for _ in 0..3 {
+        let len = u32::from_le_bytes(data.get(cursor..cursor + 4)?.try_into().ok()?) as usize;
+        cursor = cursor.checked_add(4 + len)?;
+    }
+    cursor += 2;
+    if *data.get(cursor)? == 1 {
+        let amount =
+            u32::from_le_bytes(data.get(cursor + 1..cursor + 5)?.try_into().ok()?) as usize;
+        // Creator: [address: Pubkey][verified: bool][share: u8]
+        cursor = cursor.checked_add(5 + amount.checked_mul(34)?)?;
+    } else {
+        cursor += 1;
+    }
+    cursor += 2;
+    for _ in 0..2 {
+        cursor += if *data.get(cursor)? == 1 { 2 } else { 1 };
+    }
+    if *data.get(cursor)? != 1 || *data.get(cursor + 1)? != 1 {
+        return None;
+    }
+    let key: [u8; 32] = data.get(cursor + 2..cursor + 34)?.try_into().ok()?;
+    Some(Pubkey::new_from_array(key))
+}
+
+fn artifact_in_collection(
+    artifact: &AccountInfo,
+    collection_mint: &Pubkey,
+    remaining_accounts: &[AccountInfo],
+) -> Result<bool> {
+    let mint = match read_artifact_mint(artifact)? {
+        Some(mint) => mint,
+        None => return Ok(false),
+    };
+    let (metadata_key, _) = Pubkey::find_program_address(
+        &[
+            metaplex_token_metadata::state::PREFIX.as_bytes(),
+            metaplex_token_metadata::id().as_ref(),
+            mint.as_ref(),
+        ],
+        &metaplex_token_metadata::id(),
+    );
+    let metadata = match remaining_accounts
+        .iter()
+        .find(|acct| acct.key == &metadata_key)
+    {
+        Some(metadata) => metadata,
+        None => return Ok(false),
+    };
+    if metadata.owner != &metaplex_token_metadata::id() {
+        return Ok(false);
+    }
+    Ok(read_verified_collection(metadata) == Some(*collection_mint))
+}
+
+pub fn artifact_matches_filter(
+    artifact: &AccountInfo,
+    art_namespaces: &Option<Vec<NamespaceAndIndex>>,
+    filter: &Filter,
+    evidence: &FilterEvidence,
+) -> Result<bool> {
+    match filter {
+        Filter::Namespace { namespaces } => {
+            if let Some(ns) = art_namespaces {
+                for other_n in ns {
+                    if namespaces.contains(&other_n.namespace) {
+                        return Ok(true);
+                    }
+                }
+            }
+            Ok(false)
+        }
+        Filter::Category {
+            namespace,
+            category,
+        } => {
+            let mut in_namespace = false;
+            if let Some(ns) = art_namespaces {
+                for n in ns {
+                    if n.namespace == *namespace {
+                        in_namespace = true;
+                    }
+                }
+            }
+            if !in_namespace {
+                return Ok(false);
+            }
+            if let Some(categories) = category {
+                if let Some(art_category) = read_artifact_category(artifact)? {
+                    return Ok(categories.contains(&art_category));
+                }
+                return Ok(false);
+            }
+            Ok(true)
+        }
+        Filter::Key { key, mint, .. } => {
+            if artifact.key() == *key {
+                return Ok(true);
+            }
+            Ok(read_artifact_mint(artifact)? == Some(*mint))
+        }
+        Filter::MerkleMint { root } => {
+            if let (Some(proof), Some(mint)) = (evidence.mint_proof, read_artifact_mint(artifact)?)
+            {
+                return Ok(verify(proof.to_vec(), *root, merkle_mint_leaf(&mint)));
+            }
+            Ok(false)
+        }
+        Filter::Collection { collection_mint } => {
+            artifact_in_collection(artifact, collection_mint, evidence.remaining_accounts)
+        }
+    }
+}
+
+/// Runs every filter in the gatekeeper written for this artifact type, in order,
+/// and reports whether any of them match. Filters for other artifact types are skipped.
+pub fn artifact_matches_gatekeeper<'a>(
+    artifact: &UncheckedAccount<'a>,
+    art_namespaces: &Option<Vec<NamespaceAndIndex>>,
+    namespace_gatekeeper: &UncheckedAccount<'a>,
+    artifact_type: &ArtifactType,
+    evidence: &FilterEvidence<'_, 'a>,
+) -> Result<bool> {
+    if namespace_gatekeeper.data_is_empty() {
+        return Ok(false);
+    }
+
+    let deserialized: Account<'_, NamespaceGatekeeper> =
+        Account::try_from(&namespace_gatekeeper.to_account_info())?;
+    for artifact_filter in &deserialized.artifact_filters {
+        if artifact_filter.token_type != *artifact_type {
+            continue;
+        }
+        if artifact_matches_filter(
+            &artifact.to_account_info(),
+            art_namespaces,
+            &artifact_filter.filter,
+            evidence,
+        )? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Whitelist: the artifact gets in if any filter for its type matches.
+/// Blacklist: the artifact gets in unless any filter for its type matches.
+/// Namespace: the artifact gets in only with the namespace authority's approval.
 pub fn check_permissiveness_against_holder<'a>(
     artifact: &UncheckedAccount<'a>,
-    token_holder: &UncheckedAccount<'a>,
     namespace_gatekeeper: &UncheckedAccount<'a>,
     permissiveness: &Permissiveness,
+    artifact_type: &ArtifactType,
+    namespace_authority_signed: bool,
+    evidence: &FilterEvidence<'_, 'a>,
 ) -> Result<Option<Vec<NamespaceAndIndex>>> {
     let art_namespaces = pull_namespaces(artifact)?;
     return match permissiveness {
         Permissiveness::All => Ok(art_namespaces),
         Permissiveness::Whitelist => {
-            if namespace_gatekeeper.data_is_empty() {
-                return Err(error!(ErrorCode::CannotJoinNamespace));
+            if artifact_matches_gatekeeper(
+                artifact,
+                &art_namespaces,
+                namespace_gatekeeper,
+                artifact_type,
+                evidence,
+            )? {
+                msg!("Whitelisted!");
+                Ok(art_namespaces)
             } else {
-                let deserialized: Account<'_, NamespaceGatekeeper> =
-                    Account::try_from(&namespace_gatekeeper.to_account_info())?;
-                for filter in &deserialized.artifact_filters {
-                    match &filter.filter {
-                        Filter::Namespace { namespaces } => {
-                            if let Some(ns) = &art_namespaces {
-                                for n in namespaces {
-                                    for other_n in ns {
-                                        if other_n.namespace == *n {
-                                            msg!("Whitelisted!");
-                                            return Ok(art_namespaces);
-                                        }
-                                    }
-                                }
-                            }
-                            return Err(error!(ErrorCode::CannotJoinNamespace));
-                        }
-                        Filter::Category { namespace, .. } => {
-                            if let Some(ns) = &art_namespaces {
-                                for n in ns {
-                                    if n.namespace == *namespace {
-                                        msg!("Whitelisted!");
-                                        return Ok(art_namespaces);
-                                    }
-                                }
-                            }
-                            return Err(error!(ErrorCode::CannotJoinNamespace));
-                        }
-                        Filter::Key { mint, .. } => {
-                            let as_token: spl_token::state::Account =
-                                assert_initialized(&artifact.to_account_info())?;
-
-                            if as_token.mint == *mint {
-                                msg!("Whitelisted!");
-                                return Ok(art_namespaces);
-                            }
-                            return Err(error!(ErrorCode::CannotJoinNamespace));
-                        }
-                    }
-                }
-                return Err(error!(ErrorCode::CannotJoinNamespace));
+                Err(error!(ErrorCode::CannotJoinNamespace))
             }
         }
         Permissiveness::Blacklist => {
-            if namespace_gatekeeper.data_is_empty() {
-                return Err(error!(ErrorCode::CannotJoinNamespace));
+            if artifact_matches_gatekeeper(
+                artifact,
+                &art_namespaces,
+                namespace_gatekeeper,
+                artifact_type,
+                evidence,
+            )? {
+                msg!("Blacklisted!");
+                Err(error!(ErrorCode::CannotJoinNamespace))
             } else {
-                let deserialized: Account<'_, NamespaceGatekeeper> =
-                    Account::try_from(&namespace_gatekeeper.to_account_info())?;
-                for filter in &deserialized.artifact_filters {
-                    match &filter.filter {
-                        Filter::Namespace { namespaces } => {
-                            if let Some(ns) = &art_namespaces {
-                                for n in namespaces {
-                                    for other_n in ns {
-                                        if other_n.namespace == *n {
-                                            msg!("Blacklisted!");
-                                            return Err(error!(ErrorCode::CannotJoinNamespace));
-                                        }
-                                    }
-                                }
-                            }
-                            return Ok(art_namespaces);
-                        }
-                        Filter::Category { namespace, .. } => {
-                            if let Some(ns) = &art_namespaces {
-                                for n in ns {
-                                    if n.namespace == *namespace {
-                                        msg!("Blacklisted!");
-                                        return Err(error!(ErrorCode::CannotJoinNamespace));
-                                    }
-                                }
-                            }
-                            return Ok(art_namespaces);
-                        }
-                        Filter::Key { mint, .. } => {
-                            let as_token: spl_token::state::Account =
-                                assert_initialized(&artifact.to_account_info())?;
-
-                            if as_token.mint == *mint {
-                                msg!("Blacklisted!");
-                                return Err(error!(ErrorCode::CannotJoinNamespace));
-                            }
-                            return Ok(art_namespaces);
-                        }
-                    }
-                }
-                return Err(error!(ErrorCode::CannotJoinNamespace));
+                Ok(art_namespaces)
             }
         }
         Permissiveness::Namespace => {
-            assert_signer(token_holder)?;
+            if !namespace_authority_signed {
+                return Err(error!(ErrorCode::NotNamespaceAuthority));
+            }
             return Ok(art_namespaces);
         }
     };
 }
 
+/// The permissiveness setting that governs artifacts of this type joining the namespace.
+pub fn artifact_type_permissiveness<'a>(
+    namespace: &'a Namespace,
+    artifact_type: &ArtifactType,
+) -> &'a Permissiveness {
+    match artifact_type {
+        ArtifactType::Player => &namespace.permissiveness_settings.player_permissiveness,
+        ArtifactType::Item => &namespace.permissiveness_settings.item_permissiveness,
+        ArtifactType::Mission => &namespace.permissiveness_settings.match_permissiveness,
+        ArtifactType::Namespace => &namespace.permissiveness_settings.namespace_permissiveness,
+    }
+}
+
 pub fn assert_can_add_to_namespace<'a>(
     artifact: &UncheckedAccount<'a>,
-    token_holder: &UncheckedAccount<'a>,
     namespace: &Account<'a, Namespace>,
     namespace_gatekeeper: &UncheckedAccount<'a>,
+    namespace_authority_signed: bool,
+    evidence: &FilterEvidence<'_, 'a>,
 ) -> Result<Option<Vec<NamespaceAndIndex>>> {
-    let art_namespaces = if artifact.owner == &Pubkey::from_str(crate::PLAYER_ID).unwrap() {
-        check_permissiveness_against_holder(
-            artifact,
-            token_holder,
-            namespace_gatekeeper,
-            &namespace.permissiveness_settings.player_permissiveness,
-        )?
-    } else if artifact.owner == &Pubkey::from_str(crate::ITEM_ID).unwrap() {
-        check_permissiveness_against_holder(
-            artifact,
-            token_holder,
-            namespace_gatekeeper,
-            &namespace.permissiveness_settings.item_permissiveness,
-        )?
-    } else if artifact.owner == &Pubkey::from_str(crate::MATCH_ID).unwrap() {
-        check_permissiveness_against_holder(
-            artifact,
-            token_holder,
-            namespace_gatekeeper,
-            &namespace.permissiveness_settings.match_permissiveness,
-        )?
-    } else if artifact.owner == &crate::id() {
-        check_permissiveness_against_holder(
-            artifact,
-            token_holder,
-            namespace_gatekeeper,
-            &namespace.permissiveness_settings.namespace_permissiveness,
-        )?
+    let artifact_type = get_artifact_type(&artifact.to_account_info())?;
+    check_permissiveness_against_holder(
+        artifact,
+        namespace_gatekeeper,
+        artifact_type_permissiveness(namespace, &artifact_type),
+        &artifact_type,
+        namespace_authority_signed,
+        evidence,
+    )
+}
+
+pub struct ChargeNamespaceFeeArgs<'a, 'b> {
+    pub namespace: &'b Account<'a, Namespace>,
+    pub treasury: &'b UncheckedAccount<'a>,
+    pub payer: &'b Signer<'a>,
+    pub payer_token_account: &'b UncheckedAccount<'a>,
+    pub treasury_token_account: &'b UncheckedAccount<'a>,
+    pub token_program: &'b Program<'a, Token>,
+    pub system_program: &'b Program<'a, System>,
+    pub amount: u64,
+}
+
+/// Moves a fee from the payer into the namespace treasury, in SOL or in the
+/// namespace's fee mint. SPL fees land in the treasury's associated token account, which
+/// must already exist (anyone can create it with the associated token program). A SOL fee
+/// that finds the treasury below rent exemption also tops it up, at the payer's cost.
+pub fn charge_namespace_fee(args: ChargeNamespaceFeeArgs) -> Result<()> {
+    let ChargeNamespaceFeeArgs {
+        namespace,
+        treasury,
+        payer,
+        payer_token_account,
+        treasury_token_account,
+        token_program,
+        system_program,
+        amount,
+    } = args;
+
+    let fee_mint = namespace.fee_config.as_ref().and_then(|fc| fc.fee_mint);
+    if let Some(mint) = fee_mint {
+        if treasury_token_account.key() != get_associated_token_address(&treasury.key(), &mint) {
+            return Err(error!(ErrorCode::InvalidTreasuryTokenAccount));
+        }
+        assert_owned_by(treasury_token_account, &spl_token::id())?;
+        let _treasury_token: spl_token::state::Account =
+            assert_initialized(&treasury_token_account.to_account_info())?;
+        let payer_token: spl_token::state::Account =
+            assert_initialized(&payer_token_account.to_account_info())?;
+        if payer_token.mint != mint {
+            return Err(error!(ErrorCode::InvalidFeePayerTokenAccount));
+        }
+        spl_token_transfer(TokenTransferParams {
+            source: payer_token_account.to_account_info(),
+            destination: treasury_token_account.to_account_info(),
+            amount,
+            authority: payer.to_account_info(),
+            authority_signer_seeds: &[],
+            token_program: token_program.to_account_info(),
+        })?;
     } else {
-        return Err(error!(ErrorCode::CannotJoinNamespace));
-    };
-    return Ok(art_namespaces);
+        let rent_due = Rent::get()?
+            .minimum_balance(0)
+            .saturating_sub(treasury.lamports());
+        let total = amount
+            .checked_add(rent_due)
+            .ok_or(ErrorCode::NumericalOverflowError)?;
+        invoke(
+            &system_instruction::transfer(&payer.key(), &treasury.key(), total),
+            &[
+                payer.to_account_info(),
+                treasury.to_account_info(),
+                system_program.to_account_info(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub struct AssertNamespaceAuthorityArgs<'a, 'b> {
+    pub namespace: &'b Account<'a, Namespace>,
+    pub namespace_token: &'b Account<'a, TokenAccount>,
+    pub token_holder: &'b AccountInfo<'a>,
+    /// If the authority is a multisig, the multisig account first, then any co-signers.
+    pub remaining_accounts: &'b [AccountInfo<'a>],
+    /// None means only the authority itself will do.
+    pub power: Option<NamespaceAdminPower>,
+}
+
+/// Without an explicit authority, whoever holds the namespace token is the admin.
+/// With one, the token no longer matters and the authority (or enough of its multisig
+/// signers) must sign. Delegates can stand in for the admin on the powers they hold.
+pub fn assert_namespace_authority(args: AssertNamespaceAuthorityArgs) -> Result<()> {
+    let AssertNamespaceAuthorityArgs {
+        namespace,
+        namespace_token,
+        token_holder,
+        remaining_accounts,
+        power,
+    } = args;
+
+    if token_holder.is_signer {
+        match namespace.authority {
+            Some(authority) => {
+                if authority == token_holder.key() {
+                    return Ok(());
+                }
+            }
+            None => {
+                if namespace_token.mint == namespace.mint
+                    && namespace_token.owner == token_holder.key()
+                    && namespace_token.amount == 1
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(p) = &power {
+            for delegate in &namespace.delegates {
+                if delegate.key == token_holder.key() && delegate.powers.contains(p) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    if let Some(authority) = namespace.authority {
+        if let Some(multisig_info) = remaining_accounts.first() {
+            if multisig_info.key() == authority && multisig_info.owner == &crate::id() {
+                let multisig: Account<'_, NamespaceMultisig> = Account::try_from(multisig_info)?;
+                if multisig.namespace != namespace.key() {
+                    return Err(error!(ErrorCode::NotNamespaceAuthority));
+                }
+
+                let mut approvals: Vec<Pubkey> = vec![];
+                for signer in std::iter::once(token_holder).chain(remaining_accounts[1..].iter()) {
+                    if signer.is_signer
+                        && multisig.signers.contains(signer.key)
+                        && !approvals.contains(signer.key)
+                    {
+                        approvals.push(signer.key());
+                    }
+                }
+                if approvals.len() >= multisig.threshold as usize {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    Err(error!(ErrorCode::NotNamespaceAuthority))
+}
+
+pub fn assert_valid_multisig(threshold: u8, signers: &[Pubkey]) -> Result<()> {
+    if signers.len() > MAX_MULTISIG_SIGNERS {
+        return Err(error!(ErrorCode::TooManyMultisigSigners));
+    }
+    if threshold == 0 || threshold as usize > signers.len() {
+        return Err(error!(ErrorCode::InvalidMultisigThreshold));
+    }
+    for i in 0..signers.len() {
+        if signers[i + 1..].contains(&signers[i]) {
+            return Err(error!(ErrorCode::DuplicateMultisigSigner));
+        }
+    }
+    Ok(())
 }
 pub fn assert_metadata_valid<'a>(
     metadata: &UncheckedAccount,
@@ -476,6 +1190,12 @@ pub fn assert_metadata_valid<'a>(
     Ok(())
 }
 
+/// Leaf of a MerkleMint filter's tree for this mint. The 0x00 prefix keeps leaves apart from
+/// the 0x01 prefixed inner nodes built in verify.
+pub fn merkle_mint_leaf(mint: &Pubkey) -> [u8; 32] {
+    anchor_lang::solana_program::keccak::hashv(&[&[0x00], mint.as_ref()]).0
+}
+
 /// Returns true if a `leaf` can be proved to be a part of a Merkle tree
 /// defined by `root`. For this, a `proof` must be provided, containing
 /// sibling hashes on the branch from the leaf to the root of the tree. Each
@@ -504,3 +1224,35 @@ pub fn verify(proof: Vec<[u8; 32]>, root: [u8; 32], leaf: [u8; 32]) -> bool {
     // Check if the computed hash (root) is equal to the provided root
     computed_hash == root
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(hex: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    // Tree over the mints [1; 32], [2; 32], [3; 32] and [4; 32], in that order, built off-chain.
+    const ROOT: &str = "5f50aa8c52d3544957d1d056e67bdf0fddcfa860f877a77fbe73efb6431a1c32";
+    const LEAF_4: &str = "903dfa699c483344d872c37620baf5c99d2cd033790d5dd2dcf55e0f769d4cf8";
+    const NODE_1_2: &str = "ff2578b6aefa26d6044a1db1c659eda0760bbfd17d1cc17eae8b4bcb4043f38b";
+
+    #[test]
+    fn merkle_mint_proves_listed_mint() {
+        let mint = Pubkey::new_from_array([3; 32]);
+        let proof = vec![hash(LEAF_4), hash(NODE_1_2)];
+        assert!(verify(proof, hash(ROOT), merkle_mint_leaf(&mint)));
+    }
+
+    #[test]
+    fn merkle_mint_rejects_unlisted_mint() {
+        let mint = Pubkey::new_from_array([5; 32]);
+        let proof = vec![hash(LEAF_4), hash(NODE_1_2)];
+        assert!(!verify(proof, hash(ROOT), merkle_mint_leaf(&mint)));
+    }
+}
@@ -27,9 +27,11 @@ use {
     },
 };
 
-anchor_lang::declare_id!("p1exdMJcjVao65QdewkaZRUnU6VPSXhus9n2GzWfh98");
+anchor_lang::declare_id!(raindrops_common::ids::program_id(
+    raindrops_common::ids::PLAYER_ID
+));
 
-pub const PREFIX: &str = "player";
+pub const PREFIX: &str = raindrops_common::seeds::PLAYER_PREFIX;
 #[program]
 pub mod player {
     use super::*;
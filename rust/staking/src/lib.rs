@@ -9,8 +9,10 @@ use {
     anchor_lang::{prelude::*, AnchorDeserialize, AnchorSerialize},
     anchor_spl::token::{Mint, Token, TokenAccount},
 };
-anchor_lang::declare_id!("p1exdMJcjVao65QdewkaZRUnU6VPSXhus9n2GzWfh98");
-pub const PREFIX: &str = "staking";
+anchor_lang::declare_id!(raindrops_common::ids::program_id(
+    raindrops_common::ids::STAKING_ID
+));
+pub const PREFIX: &str = raindrops_common::seeds::STAKING_PREFIX;
 pub const STAKING_COUNTER: &str = "counter";
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -95,6 +97,11 @@ pub mod staking {
             index,
         )?;
 
+        let namespace_accounts = [&[namespace.to_account_info()], ctx.remaining_accounts].concat();
+        raindrops_namespace::utils::assert_not_frozen_in_namespaces(
+            &artifact_unchecked.to_account_info(),
+            &namespace_accounts,
+        )?;
         let namespace = assert_part_of_namespace(&artifact_unchecked.to_account_info(), namespace)?;
 
         assert_permissiveness_access(AssertPermissivenessAccessArgs {
@@ -215,6 +222,17 @@ pub mod staking {
             .checked_add(staking_amount)
             .ok_or(ErrorCode::NumericalOverflowError)?;
 
+        raindrops_namespace::utils::record_stat_in_namespaces(
+            raindrops_namespace::utils::RecordStatInNamespacesArgs {
+                artifact: &artifact_unchecked.to_account_info(),
+                stat: raindrops_namespace::NamespaceStat::TokensStaked {
+                    amount: staking_amount,
+                },
+                program_id: ctx.program_id,
+                remaining_accounts: ctx.remaining_accounts,
+            },
+        )?;
+
         // Because artifact is using a copy of this data
         let mut data = artifact_unchecked.data.borrow_mut();
         data.copy_from_slice(&artifact.try_to_vec()?);
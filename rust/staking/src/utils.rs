@@ -252,16 +252,12 @@ pub fn assert_permissiveness_access(args: AssertPermissivenessAccessArgs) -> Res
 }
 
 pub fn grab_parent<'a>(artifact: &AccountInfo<'a>) -> Result<Pubkey> {
+    let offset =
+        raindrops_namespace::artifact::ArtifactNamespaces::load(artifact)?.end_offset();
     let data = artifact.data.borrow();
 
-    let number = if data[8] == 1 {
-        u32::from_le_bytes(*array_ref![data, 9, 4]) as usize
-    } else {
-        0
-    };
-    let offset = 8 as usize + number * 34 + if data[8] == 1 { 4 } else { 0 };
-
-    if data[offset] == 1 {
+    // Option tag on classes. On items it is the layout version, which is never 0.
+    if data[offset] != 0 {
         let key_bytes = array_ref![data, offset + 1, 32];
         let key = Pubkey::new_from_array(*key_bytes);
         return Ok(key);